@@ -0,0 +1,23 @@
+/// Formulae listed by `brew autoremove --dry-run` under its `==> Would autoremove N unneeded formulae:` header
+pub fn orphans(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("==> Would autoremove"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("==>"))
+        .flat_map(str::split_whitespace)
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_list_formulae_under_dry_run_header() {
+        let output = "==> Would autoremove 3 unneeded formulae:\nlibyaml\nm4\npcre\n";
+        assert_eq!(orphans(output), vec!["libyaml", "m4", "pcre"]);
+        assert!(orphans("").is_empty());
+    }
+}
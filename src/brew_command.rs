@@ -6,9 +6,34 @@ use thiserror::Error;
 #[derive(Debug, Clone, PartialEq)]
 pub enum BrewCommand<'a> {
     Update { envs: HashMap<&'static str, String> },
-    Outdated { envs: HashMap<&'static str, String> },
-    Upgrade { package_name: &'a str, envs: HashMap<&'static str, String> },
-    Cleanup { envs: HashMap<&'static str, String> },
+    Outdated { greedy: Option<Greedy>, envs: HashMap<&'static str, String> },
+    Info { envs: HashMap<&'static str, String> },
+    Fetch { package_name: &'a str, kind: PackageKind, envs: HashMap<&'static str, String> },
+    Upgrade { package_names: Vec<&'a str>, kind: PackageKind, greedy: Option<Greedy>, envs: HashMap<&'static str, String> },
+    Cleanup { prune: Option<&'a str>, scrub: bool, envs: HashMap<&'static str, String> },
+    Uses { package_name: &'a str, envs: HashMap<&'static str, String> },
+    Linkage { package_name: &'a str, envs: HashMap<&'static str, String> },
+    Reinstall { package_name: &'a str, envs: HashMap<&'static str, String> },
+    ServicesList { envs: HashMap<&'static str, String> },
+    ServicesRestart { service_name: &'a str, envs: HashMap<&'static str, String> },
+    Unlink { package_name: &'a str, envs: HashMap<&'static str, String> },
+    Link { package_name: &'a str, envs: HashMap<&'static str, String> },
+    Pin { package_name: &'a str, envs: HashMap<&'static str, String> },
+    Unpin { package_name: &'a str, envs: HashMap<&'static str, String> },
+    ListPinned { envs: HashMap<&'static str, String> },
+    BundleDump { envs: HashMap<&'static str, String> },
+    BundleCheck { brewfile: &'a str, envs: HashMap<&'static str, String> },
+    BundleInstall { brewfile: &'a str, envs: HashMap<&'static str, String> },
+    Taps { envs: HashMap<&'static str, String> },
+    TapInfo { envs: HashMap<&'static str, String> },
+    Untap { tap: &'a str, envs: HashMap<&'static str, String> },
+    Leaves { envs: HashMap<&'static str, String> },
+    Install { package_name: &'a str, kind: PackageKind, envs: HashMap<&'static str, String> },
+    Uninstall { package_name: &'a str, kind: PackageKind, envs: HashMap<&'static str, String> },
+    Autoremove { dry_run: bool, envs: HashMap<&'static str, String> },
+    CachePath { envs: HashMap<&'static str, String> },
+    Doctor { envs: HashMap<&'static str, String> },
+    Config { envs: HashMap<&'static str, String> },
 }
 
 impl<'a> BrewCommand<'a> {
@@ -18,16 +43,105 @@ impl<'a> BrewCommand<'a> {
             BrewCommand::Update { envs: _ } => {
                 vec!["update"]
             }
-            BrewCommand::Outdated { envs: _ } => {
-                vec!["outdated", "--json"]
+            BrewCommand::Outdated { greedy: None, envs: _ } => {
+                vec!["outdated", "--json=v2"]
             }
-            BrewCommand::Upgrade { package_name, envs: _ } => {
-                let mut args = vec!["upgrade"];
-                args.push(package_name);
+            BrewCommand::Outdated { greedy: Some(greedy), envs: _ } => {
+                vec!["outdated", "--json=v2", "--cask", greedy.flag()]
+            }
+            BrewCommand::Info { envs: _ } => {
+                vec!["info", "--json=v2", "--installed"]
+            }
+            BrewCommand::Fetch { package_name, kind, envs: _ } => {
+                vec!["fetch", kind.flag(), package_name]
+            }
+            BrewCommand::Upgrade { package_names, kind, greedy, envs: _ } => {
+                let mut args = vec!["upgrade", kind.flag()];
+                args.extend(greedy.map(|g| g.flag()));
+                args.extend(package_names);
                 args
             }
-            BrewCommand::Cleanup { envs: _ } => {
-                vec!["cleanup"]
+            BrewCommand::Cleanup { prune, scrub, envs: _ } => {
+                let mut args = vec!["cleanup"];
+                if let Some(days) = prune {
+                    args.extend(["--prune", days]);
+                }
+                if *scrub {
+                    args.push("-s");
+                }
+                args
+            }
+            BrewCommand::Uses { package_name, envs: _ } => {
+                vec!["uses", "--installed", "--recursive", "--formula", package_name]
+            }
+            BrewCommand::Linkage { package_name, envs: _ } => {
+                vec!["linkage", "--test", package_name]
+            }
+            BrewCommand::Reinstall { package_name, envs: _ } => {
+                vec!["reinstall", "--formula", package_name]
+            }
+            BrewCommand::ServicesList { envs: _ } => {
+                vec!["services", "list", "--json"]
+            }
+            BrewCommand::ServicesRestart { service_name, envs: _ } => {
+                vec!["services", "restart", service_name]
+            }
+            BrewCommand::Unlink { package_name, envs: _ } => {
+                vec!["unlink", package_name]
+            }
+            BrewCommand::Link { package_name, envs: _ } => {
+                vec!["link", package_name]
+            }
+            BrewCommand::Pin { package_name, envs: _ } => {
+                vec!["pin", package_name]
+            }
+            BrewCommand::Unpin { package_name, envs: _ } => {
+                vec!["unpin", package_name]
+            }
+            BrewCommand::ListPinned { envs: _ } => {
+                vec!["list", "--pinned"]
+            }
+            BrewCommand::BundleDump { envs: _ } => {
+                vec!["bundle", "dump", "--file=-"]
+            }
+            BrewCommand::BundleCheck { brewfile, envs: _ } => {
+                vec!["bundle", "check", "--no-upgrade", "--file", brewfile]
+            }
+            BrewCommand::BundleInstall { brewfile, envs: _ } => {
+                vec!["bundle", "install", "--no-upgrade", "--file", brewfile]
+            }
+            BrewCommand::Taps { envs: _ } => {
+                vec!["tap"]
+            }
+            BrewCommand::TapInfo { envs: _ } => {
+                vec!["tap-info", "--json", "--installed"]
+            }
+            BrewCommand::Untap { tap, envs: _ } => {
+                vec!["untap", tap]
+            }
+            BrewCommand::Leaves { envs: _ } => {
+                vec!["leaves", "--installed-on-request"]
+            }
+            BrewCommand::Install { package_name, kind, envs: _ } => {
+                vec!["install", kind.flag(), package_name]
+            }
+            BrewCommand::Uninstall { package_name, kind, envs: _ } => {
+                vec!["uninstall", kind.flag(), package_name]
+            }
+            BrewCommand::Autoremove { dry_run: false, envs: _ } => {
+                vec!["autoremove"]
+            }
+            BrewCommand::Autoremove { dry_run: true, envs: _ } => {
+                vec!["autoremove", "--dry-run"]
+            }
+            BrewCommand::CachePath { envs: _ } => {
+                vec!["--cache"]
+            }
+            BrewCommand::Doctor { envs: _ } => {
+                vec!["doctor"]
+            }
+            BrewCommand::Config { envs: _ } => {
+                vec!["config"]
             }
         }
     }
@@ -35,25 +149,91 @@ impl<'a> BrewCommand<'a> {
     pub fn to_env(&self) -> HashMap<&'static str, String> {
         match self {
             BrewCommand::Update { envs } => envs.clone(),
-            BrewCommand::Outdated { envs } => envs.clone(),
-            BrewCommand::Upgrade { package_name: _, envs } => envs.clone(),
-            BrewCommand::Cleanup { envs } => envs.clone(),
+            BrewCommand::Outdated { greedy: _, envs } => envs.clone(),
+            BrewCommand::Info { envs } => envs.clone(),
+            BrewCommand::Fetch { package_name: _, kind: _, envs } => envs.clone(),
+            BrewCommand::Upgrade { package_names: _, kind: _, greedy: _, envs } => envs.clone(),
+            BrewCommand::Cleanup { prune: _, scrub: _, envs } => envs.clone(),
+            BrewCommand::Uses { package_name: _, envs } => envs.clone(),
+            BrewCommand::Linkage { package_name: _, envs } => envs.clone(),
+            BrewCommand::Reinstall { package_name: _, envs } => envs.clone(),
+            BrewCommand::ServicesList { envs } => envs.clone(),
+            BrewCommand::ServicesRestart { service_name: _, envs } => envs.clone(),
+            BrewCommand::Unlink { package_name: _, envs } => envs.clone(),
+            BrewCommand::Link { package_name: _, envs } => envs.clone(),
+            BrewCommand::Pin { package_name: _, envs } => envs.clone(),
+            BrewCommand::Unpin { package_name: _, envs } => envs.clone(),
+            BrewCommand::ListPinned { envs } => envs.clone(),
+            BrewCommand::BundleDump { envs } => envs.clone(),
+            BrewCommand::BundleCheck { brewfile: _, envs } => envs.clone(),
+            BrewCommand::BundleInstall { brewfile: _, envs } => envs.clone(),
+            BrewCommand::Taps { envs } => envs.clone(),
+            BrewCommand::TapInfo { envs } => envs.clone(),
+            BrewCommand::Untap { tap: _, envs } => envs.clone(),
+            BrewCommand::Leaves { envs } => envs.clone(),
+            BrewCommand::Install { package_name: _, kind: _, envs } => envs.clone(),
+            BrewCommand::Uninstall { package_name: _, kind: _, envs } => envs.clone(),
+            BrewCommand::Autoremove { dry_run: _, envs } => envs.clone(),
+            BrewCommand::CachePath { envs } => envs.clone(),
+            BrewCommand::Doctor { envs } => envs.clone(),
+            BrewCommand::Config { envs } => envs.clone(),
+        }
+    }
+}
+
+/// Disambiguates a formula and a cask sharing the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageKind {
+    Formula,
+    Cask,
+}
+
+impl PackageKind {
+    pub fn flag(&self) -> &'static str {
+        match self {
+            PackageKind::Formula => "--formula",
+            PackageKind::Cask => "--cask",
+        }
+    }
+}
+
+/// Includes casks that brew considers up to date because they update themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Greedy {
+    /// Both `auto_updates true` and `version :latest` casks
+    All,
+    AutoUpdates,
+    Latest,
+}
+
+impl Greedy {
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Greedy::All => "--greedy",
+            Greedy::AutoUpdates => "--greedy-auto-updates",
+            Greedy::Latest => "--greedy-latest",
         }
     }
 }
 
 #[derive(Debug, Error)]
 pub enum BrewError {
-    #[error("Error executing the brew command")]
+    #[error("Error executing the brew command: {0}")]
     ExecutionFailed(String),
+    /// The command exited with an error after printing `output` on its standard output
+    #[error("Error executing the brew command: {reason}")]
+    FailedWithOutput { reason: String, output: String },
     #[error("Error Input request cannot be fulfilled")]
     InputRequested,
     #[error("Error command takes more than the timeout requested")]
     Timeout,
+    #[error("Error parsing the brew output: {reason} in {snippet:?}")]
+    ParseFailed { reason: String, snippet: String },
 }
 
 pub trait CommandExecutor {
     fn execute(&self, cmd: &BrewCommand) -> Result<String, BrewError>;
     fn envs(&self) -> HashMap<&'static str, String>;
-    async fn execute_with_timeout<'a>(&self, cmd: &BrewCommand<'a>, timeout: Duration) -> Result<(), BrewError>;
+    /// Runs the command, killing it once `timeout` elapses or when it asks for input; returns its standard output
+    async fn execute_with_timeout<'a>(&self, cmd: &BrewCommand<'a>, timeout: Duration) -> Result<String, BrewError>;
 }
@@ -0,0 +1,150 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use crate::info::InstalledInfo;
+
+/// Taps, formulae and casks declared by a Brewfile; other entries (`mas`, `vscode`, ...) are left to `brew bundle`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Brewfile {
+    pub taps: BTreeSet<String>,
+    pub formulae: BTreeSet<String>,
+    pub casks: BTreeSet<String>,
+}
+
+impl Brewfile {
+    pub fn parse(content: &str) -> Self {
+        let mut brewfile = Brewfile::default();
+        for line in content.lines().map(str::trim) {
+            let Some((keyword, arguments)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some(name) = first_string(arguments) else {
+                continue;
+            };
+            match keyword {
+                "tap" => brewfile.taps.insert(name),
+                "brew" => brewfile.formulae.insert(name),
+                "cask" => brewfile.casks.insert(name),
+                _ => false,
+            };
+        }
+        brewfile
+    }
+
+    /// Whether the formula is declared. Either side may be a fully qualified `user/tap/name` (as `brew leaves` prints
+    /// formulae of third-party taps): short names are compared unless both are qualified
+    pub fn declares_formula(&self, name: &str) -> bool {
+        let short = |name: &'_ str| name.rsplit('/').next().unwrap_or_default().to_string();
+        self.formulae.iter().any(|declared| {
+            declared == name || ((!declared.contains('/') || !name.contains('/')) && short(declared) == short(name))
+        })
+    }
+
+    /// Declared packages that are not installed, and installed leaves that are not declared
+    pub fn diff(&self, installed_taps: &[String], info: &InstalledInfo, leaves: &[String]) -> BrewfileDiff {
+        BrewfileDiff {
+            missing_taps: self.taps.iter().filter(|tap| !installed_taps.contains(tap)).cloned().collect(),
+            missing_formulae: self.formulae.iter().filter(|name| info.formula(name).is_none()).cloned().collect(),
+            missing_casks: self.casks.iter().filter(|token| info.cask(token).is_none()).cloned().collect(),
+            unlisted_leaves: leaves.iter().filter(|leaf| !self.declares_formula(leaf)).cloned().collect(),
+        }
+    }
+}
+
+/// First double quoted argument of a Brewfile entry, e.g. `jq` in `brew "jq", args: ["HEAD"]`
+fn first_string(arguments: &str) -> Option<String> {
+    let (_, rest) = arguments.trim_start().split_once('"')?;
+    let (name, _) = rest.split_once('"')?;
+    Some(name.to_string())
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrewfileDiff {
+    pub missing_taps: Vec<String>,
+    pub missing_formulae: Vec<String>,
+    pub missing_casks: Vec<String>,
+    /// Formulae installed on request that nothing depends on and the Brewfile does not declare
+    pub unlisted_leaves: Vec<String>,
+}
+
+impl BrewfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing_taps.is_empty()
+            && self.missing_formulae.is_empty()
+            && self.missing_casks.is_empty()
+            && self.unlisted_leaves.is_empty()
+    }
+}
+
+impl Display for BrewfileDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for tap in &self.missing_taps {
+            writeln!(f, "\t + tap {}", tap)?;
+        }
+        for formula in &self.missing_formulae {
+            writeln!(f, "\t + brew {}", formula)?;
+        }
+        for cask in &self.missing_casks {
+            writeln!(f, "\t + cask {}", cask)?;
+        }
+        for leaf in &self.unlisted_leaves {
+            writeln!(f, "\t - brew {} (not in the Brewfile)", leaf)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BREWFILE: &str = r#"
+# team toolchain
+tap "homebrew/bundle"
+tap "hashicorp/tap"
+brew "jq"
+brew "hashicorp/tap/terraform", link: true
+brew "postgresql@16", restart_service: :changed
+cask "firefox"
+mas "Xcode", id: 497799835
+"#;
+
+    #[test]
+    fn should_parse_taps_formulae_and_casks_ignoring_other_entries() {
+        let brewfile = Brewfile::parse(BREWFILE);
+        assert_eq!(brewfile.taps.len(), 2);
+        assert_eq!(brewfile.formulae.iter().collect::<Vec<_>>(), vec!["hashicorp/tap/terraform", "jq", "postgresql@16"]);
+        assert_eq!(brewfile.casks.iter().collect::<Vec<_>>(), vec!["firefox"]);
+        assert!(brewfile.declares_formula("terraform"));
+        assert!(brewfile.declares_formula("hashicorp/tap/terraform"));
+        assert!(!brewfile.declares_formula("acme/tap/terraform"));
+        assert!(Brewfile::parse("brew \"jq\"").declares_formula("homebrew/core/jq"));
+    }
+
+    #[test]
+    fn should_list_missing_entries_and_unlisted_leaves() {
+        let brewfile = Brewfile::parse(BREWFILE);
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{
+                "formulae": [{"name": "jq", "full_name": "jq"}, {"name": "terraform", "full_name": "hashicorp/tap/terraform"},
+                             {"name": "wget", "full_name": "wget"}],
+                "casks": [{"token": "firefox", "full_token": "firefox"}]
+            }"#,
+        )
+        .unwrap();
+        let taps = vec!["homebrew/bundle".to_string(), "hashicorp/tap".to_string()];
+        let leaves = vec!["jq".to_string(), "hashicorp/tap/terraform".to_string(), "wget".to_string()];
+
+        let diff = brewfile.diff(&taps, &info, &leaves);
+
+        assert_eq!(
+            diff,
+            BrewfileDiff {
+                missing_taps: vec![],
+                missing_formulae: vec!["postgresql@16".to_string()],
+                missing_casks: vec![],
+                unlisted_leaves: vec!["wget".to_string()],
+            }
+        );
+        assert!(!diff.is_empty());
+    }
+}
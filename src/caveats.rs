@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+/// Caveats printed by `brew upgrade` for each of the upgraded `packages`.
+///
+/// brew prints a `==> Caveats` section while installing each package, after its `==> Upgrading <name>` header, and
+/// repeats them at the end of a multi-package upgrade as a single `==> Caveats` section split by `==> <name>` headers.
+/// Repeated sections are kept once; when a single package was upgraded every section belongs to it.
+pub fn caveats(output: &str, packages: &[&str]) -> BTreeMap<String, String> {
+    let mut caveats: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut upgrading: Option<&str> = packages.first().filter(|_| packages.len() == 1).copied();
+    let mut current: Option<(Option<&str>, Vec<&str>)> = None;
+    for line in output.lines().chain(std::iter::once("==>")) {
+        let Some(header) = line.strip_prefix("==>").map(str::trim) else {
+            if let Some((_, lines)) = current.as_mut() {
+                lines.push(line);
+            }
+            continue;
+        };
+        if let Some((package, lines)) = current.take() {
+            let text = lines.join("\n").trim().to_string();
+            if let Some(package) = package.filter(|_| !text.is_empty()) {
+                let sections = caveats.entry(package.to_string()).or_default();
+                if !sections.contains(&text) {
+                    sections.push(text);
+                }
+            }
+        }
+        if header == "Caveats" {
+            current = Some((upgrading, vec![]));
+        } else if let Some(name) = header.strip_prefix("Upgrading ").and_then(|rest| rest.split_whitespace().next()) {
+            upgrading = packages.iter().find(|p| **p == name).copied().or(upgrading);
+        } else if let Some(package) = packages.iter().find(|p| **p == header) {
+            // a package header of the summary section: what follows are its caveats
+            upgrading = Some(package);
+            current = Some((upgrading, vec![]));
+        }
+    }
+    caveats.into_iter().map(|(package, sections)| (package, sections.join("\n"))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_attribute_caveats_of_a_single_upgrade() {
+        let output = "==> Upgrading postgresql@16\n  16.3 -> 16.4\n==> Pouring postgresql@16--16.4.arm64_sequoia.bottle.tar.gz\n\
+            ==> Caveats\nTo restart postgresql@16 after an upgrade:\n  brew services restart postgresql@16\n\
+            ==> Summary\n🍺  /opt/homebrew/Cellar/postgresql@16/16.4: 3,811 files, 68.5MB\n";
+        let caveats = caveats(output, &["postgresql@16"]);
+        assert_eq!(
+            caveats.get("postgresql@16").map(String::as_str),
+            Some("To restart postgresql@16 after an upgrade:\n  brew services restart postgresql@16")
+        );
+        assert_eq!(caveats.len(), 1);
+    }
+
+    #[test]
+    fn should_split_caveats_of_a_batch_and_drop_repeated_summary() {
+        let output = "==> Upgrading 3 outdated packages:\nnode 22.1.0 -> 22.2.0\nredis 7.2.4 -> 7.2.5\njq 1.7 -> 1.7.1\n\
+            ==> Upgrading node\n  22.1.0 -> 22.2.0\n==> Caveats\nBash completion has been installed to:\n  /opt/homebrew/etc/bash_completion.d\n\
+            ==> Summary\n🍺  /opt/homebrew/Cellar/node/22.2.0: 2,000 files, 70MB\n\
+            ==> Upgrading redis\n  7.2.4 -> 7.2.5\n==> Caveats\nTo restart redis after an upgrade:\n  brew services restart redis\n\
+            ==> Summary\n🍺  /opt/homebrew/Cellar/redis/7.2.5: 14 files, 2.4MB\n\
+            ==> Upgrading jq\n  1.7 -> 1.7.1\n==> Summary\n🍺  /opt/homebrew/Cellar/jq/1.7.1: 19 files, 1.3MB\n\
+            ==> Caveats\n==> node\nBash completion has been installed to:\n  /opt/homebrew/etc/bash_completion.d\n\
+            ==> redis\nTo restart redis after an upgrade:\n  brew services restart redis\n";
+        let caveats = caveats(output, &["node", "redis", "jq"]);
+        assert_eq!(
+            caveats.get("node").map(String::as_str),
+            Some("Bash completion has been installed to:\n  /opt/homebrew/etc/bash_completion.d")
+        );
+        assert_eq!(
+            caveats.get("redis").map(String::as_str),
+            Some("To restart redis after an upgrade:\n  brew services restart redis")
+        );
+        assert!(!caveats.contains_key("jq"));
+    }
+
+    #[test]
+    fn should_find_nothing_without_caveats() {
+        assert!(caveats("==> Upgrading jq\n  1.7 -> 1.7.1\n==> Summary\n", &["jq"]).is_empty());
+        assert!(caveats("", &[]).is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+use regex::Regex;
+
+const UNITS: [(&str, u64); 4] = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)];
+
+/// Disk space reported by `brew cleanup` in its `This operation has freed approximately 1.2GB of disk space.` line
+pub fn reclaimed_bytes(output: &str) -> Option<u64> {
+    let pattern = Regex::new(r"freed approximately ([0-9.]+)\s*(GB|MB|KB|B)\b").ok()?;
+    let captures = pattern.captures(output)?;
+    let amount: f64 = captures[1].parse().ok()?;
+    let (_, multiplier) = UNITS.iter().find(|(unit, _)| *unit == &captures[2])?;
+    Some((amount * *multiplier as f64).round() as u64)
+}
+
+/// Sizes the way brew prints them, e.g. `1.2GB`
+pub fn format_bytes(bytes: u64) -> String {
+    let (unit, multiplier) = UNITS.iter().find(|(_, multiplier)| bytes >= *multiplier).unwrap_or(&UNITS[3]);
+    if *multiplier == 1 { format!("{}{}", bytes, unit) } else { format!("{:.1}{}", bytes as f64 / *multiplier as f64, unit) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_freed_space_of_brew_cleanup() {
+        let output = "Removing: /opt/homebrew/Cellar/jq/1.7.1... (19 files, 1.1MB)\n\
+                      ==> This operation has freed approximately 1.5GB of disk space.\n";
+        assert_eq!(reclaimed_bytes(output), Some(1_610_612_736));
+        assert_eq!(reclaimed_bytes("==> This operation has freed approximately 512B of disk space."), Some(512));
+        assert_eq!(reclaimed_bytes("Nothing to clean"), None);
+    }
+
+    #[test]
+    fn should_format_bytes_with_largest_unit() {
+        assert_eq!(format_bytes(1_610_612_736), "1.5GB");
+        assert_eq!(format_bytes(2048), "2.0KB");
+        assert_eq!(format_bytes(0), "0B");
+    }
+}
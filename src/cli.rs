@@ -0,0 +1,85 @@
+use anyhow::{Context, Result, bail};
+
+pub const USAGE: &str = "usage: brew-maintainer [run | rollback <formula> | pin <formula> [<days>] | unpin <formula> \
+    | diff [<snapshot> <snapshot>] | drift [<lockfile or directory>]]";
+
+/// Longest pin accepted on the command line, a century
+const MAX_PIN_DAYS: u32 = 36_500;
+
+/// Action requested on the command line, a maintenance run when none is given
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run,
+    /// Relink the keg replaced by the last upgrade of the formula and pin it
+    Rollback {
+        package_name: String,
+    },
+    /// Pin the formula, for the given number of days or for good
+    Pin {
+        package_name: String,
+        days: Option<u32>,
+    },
+    Unpin {
+        package_name: String,
+    },
+    /// Compare two snapshots, the two most recent ones when none is given
+    Diff {
+        snapshots: Option<(String, String)>,
+    },
+    /// Compare the local lockfile with a reference lockfile or a directory of fleet lockfiles
+    Drift {
+        reference: Option<String>,
+    },
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let args: Vec<String> = args.into_iter().collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] | ["run"] => Ok(Command::Run),
+        ["rollback", package_name] => Ok(Command::Rollback { package_name: package_name.to_string() }),
+        ["pin", package_name] => Ok(Command::Pin { package_name: package_name.to_string(), days: None }),
+        ["pin", package_name, days] => {
+            let days: u32 = days.parse().with_context(|| format!("invalid number of days {:?}\n{}", days, USAGE))?;
+            if days > MAX_PIN_DAYS {
+                bail!("cannot pin for more than {} days, pin without a number of days to pin for good\n{}", MAX_PIN_DAYS, USAGE);
+            }
+            Ok(Command::Pin { package_name: package_name.to_string(), days: Some(days) })
+        }
+        ["unpin", package_name] => Ok(Command::Unpin { package_name: package_name.to_string() }),
+        ["diff"] => Ok(Command::Diff { snapshots: None }),
+        ["diff", before, after] => Ok(Command::Diff { snapshots: Some((before.to_string(), after.to_string())) }),
+        ["drift"] => Ok(Command::Drift { reference: None }),
+        ["drift", reference] => Ok(Command::Drift { reference: Some(reference.to_string()) }),
+        _ => bail!("unexpected arguments {:?}\n{}", args, USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn should_parse_run_and_rollback_commands() {
+        assert_eq!(parse(args(&[])).unwrap(), Command::Run);
+        assert_eq!(parse(args(&["run"])).unwrap(), Command::Run);
+        assert_eq!(parse(args(&["rollback", "jq"])).unwrap(), Command::Rollback { package_name: "jq".to_string() });
+        assert!(parse(args(&["rollback"])).is_err());
+        assert_eq!(parse(args(&["pin", "node"])).unwrap(), Command::Pin { package_name: "node".to_string(), days: None });
+        assert_eq!(parse(args(&["pin", "node", "30"])).unwrap(), Command::Pin { package_name: "node".to_string(), days: Some(30) });
+        assert!(parse(args(&["pin", "node", "soon"])).is_err());
+        assert!(parse(args(&["pin", "node", "4294967295"])).is_err());
+        assert_eq!(parse(args(&["unpin", "node"])).unwrap(), Command::Unpin { package_name: "node".to_string() });
+        assert_eq!(parse(args(&["diff"])).unwrap(), Command::Diff { snapshots: None });
+        assert_eq!(
+            parse(args(&["diff", "a", "b"])).unwrap(),
+            Command::Diff { snapshots: Some(("a".to_string(), "b".to_string())) }
+        );
+        assert!(parse(args(&["diff", "a"])).is_err());
+        assert_eq!(parse(args(&["drift", "fleet"])).unwrap(), Command::Drift { reference: Some("fleet".to_string()) });
+        assert!(parse(args(&["upgrade", "jq"])).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{brew_command::Greedy, history::History, smoke_tests::SmokeTest};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub timeouts: TimeoutConfig,
+    pub upgrade: UpgradeConfig,
+    pub fetch: FetchConfig,
+    pub source_builds: SourceBuildConfig,
+    pub casks: CaskConfig,
+    pub running_programs: RunningProgramsConfig,
+    pub services: ServicesConfig,
+    /// Commands checking each package after it is upgraded
+    pub smoke_tests: HashMap<String, Vec<SmokeTest>>,
+    pub linkage: LinkageConfig,
+    pub rollback: RollbackConfig,
+    pub snapshots: SnapshotConfig,
+    pub reconcile: ReconcileConfig,
+    pub drift: DriftConfig,
+    pub autoremove: AutoremoveConfig,
+    pub cleanup: CleanupConfig,
+    pub disk_space: DiskSpaceConfig,
+    pub health: HealthConfig,
+    pub deprecations: DeprecationConfig,
+    /// Formulae kept pinned by every run, until the end of the optional date
+    pub pins: HashMap<String, PinRule>,
+    pub taps: TapConfig,
+}
+
+impl Config {
+    /// Loads the configuration file, falling back to the defaults when it does not exist
+    pub fn load(path: &Path) -> Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).with_context(|| format!("invalid configuration in {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).with_context(|| format!("cannot read configuration {}", path.display())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Timeout used for packages without any recorded upgrade
+    pub default_minutes: u64,
+    /// Factor applied to the slowest recorded upgrade of a package
+    pub multiplier: f64,
+    pub floor_minutes: u64,
+    pub ceiling_minutes: u64,
+    /// Per-package timeouts, taking precedence over history
+    pub overrides: HashMap<String, u64>,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self { default_minutes: 5, multiplier: 3.0, floor_minutes: 2, ceiling_minutes: 240, overrides: HashMap::new() }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UpgradeConfig {
+    pub mode: UpgradeMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpgradeMode {
+    /// One `brew upgrade` per package
+    #[default]
+    Serial,
+    /// A single `brew upgrade` for the whole plan, bisected on failure to find the culprits
+    Batch,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    /// Download every planned package before upgrading
+    pub enabled: bool,
+    /// Maximum number of concurrent `brew fetch`
+    pub concurrency: usize,
+    pub timeout_minutes: u64,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self { enabled: true, concurrency: 4, timeout_minutes: 15 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceBuildConfig {
+    /// What to do with packages that have no bottle for this machine
+    pub policy: SourceBuildPolicy,
+    /// Days on which deferred source builds are allowed (e.g. `sat`, `sunday`)
+    pub window_days: Vec<String>,
+    /// Timeout granted to source builds
+    pub timeout_minutes: u64,
+}
+
+impl Default for SourceBuildConfig {
+    fn default() -> Self {
+        Self { policy: SourceBuildPolicy::Allow, window_days: vec!["sat".to_string(), "sun".to_string()], timeout_minutes: 240 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceBuildPolicy {
+    /// Never build from source
+    Skip,
+    /// Build from source only during the window days
+    Defer,
+    /// Always build from source, with the source build timeout
+    #[default]
+    Allow,
+}
+
+/// Casks to upgrade even though they update themselves, selected per cask
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CaskConfig {
+    /// Casks upgraded with `--greedy`
+    pub greedy: Vec<String>,
+    /// Casks upgraded with `--greedy-auto-updates`
+    pub greedy_auto_updates: Vec<String>,
+    /// Casks upgraded with `--greedy-latest`
+    pub greedy_latest: Vec<String>,
+}
+
+impl CaskConfig {
+    pub fn greedy_for(&self, cask_name: &str) -> Option<Greedy> {
+        self.selections().find(|(_, casks)| casks.iter().any(|c| c == cask_name)).map(|(greedy, _)| greedy)
+    }
+
+    /// Greedy modes with the casks selected for each of them
+    pub fn selections(&self) -> impl Iterator<Item = (Greedy, &Vec<String>)> {
+        [(Greedy::All, &self.greedy), (Greedy::AutoUpdates, &self.greedy_auto_updates), (Greedy::Latest, &self.greedy_latest)]
+            .into_iter()
+            .filter(|(_, casks)| !casks.is_empty())
+    }
+}
+
+/// What to do with packages whose programs are running when they are about to be upgraded
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RunningProgramsConfig {
+    pub policy: RunningProgramPolicy,
+    /// Per-package policies, taking precedence over `policy`
+    pub packages: HashMap<String, RunningProgramPolicy>,
+}
+
+impl RunningProgramsConfig {
+    pub fn policy_for(&self, package_name: &str) -> RunningProgramPolicy {
+        self.packages.get(package_name).copied().unwrap_or(self.policy)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunningProgramPolicy {
+    /// Upgrade anyway
+    Ignore,
+    /// Do not upgrade in this run
+    Skip,
+    /// Retry at the end of the upgrade phase, skip if still running
+    #[default]
+    Defer,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServicesConfig {
+    /// Restart the started brew services of upgraded formulae
+    pub restart: bool,
+    /// Number of `brew services list` checks waiting for a restarted service to be `started`
+    pub verify_attempts: u32,
+    pub verify_interval_seconds: u64,
+    /// Time `brew services restart` is given before it is killed
+    pub restart_timeout_seconds: u64,
+}
+
+impl Default for ServicesConfig {
+    fn default() -> Self {
+        Self { restart: true, verify_attempts: 5, verify_interval_seconds: 2, restart_timeout_seconds: 60 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LinkageConfig {
+    /// Run `brew linkage --test` on the installed dependents of upgraded formulae
+    pub verify: bool,
+    /// Reinstall the dependents with broken linkage
+    pub reinstall_broken: bool,
+}
+
+impl Default for LinkageConfig {
+    fn default() -> Self {
+        Self { verify: true, reinstall_broken: false }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RollbackConfig {
+    /// Days the keg replaced by an upgrade is kept from `brew cleanup`, 0 lets brew remove it right away
+    pub retention_days: u64,
+}
+
+impl Default for RollbackConfig {
+    fn default() -> Self {
+        Self { retention_days: 7 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Capture the installed packages before and after each run
+    pub enabled: bool,
+    /// Number of most recent snapshots kept
+    pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self { enabled: true, keep: 60 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReconcileConfig {
+    /// Shared Brewfile the machine converges to, reconciliation is disabled without it
+    pub brewfile: Option<PathBuf>,
+    /// Additional Brewfiles keyed by short host name
+    pub overlays: HashMap<String, PathBuf>,
+    /// Report the differences without installing or uninstalling anything
+    pub report_only: bool,
+    /// Uninstall the formulae installed on request that nothing depends on and no Brewfile declares
+    pub uninstall_unlisted_leaves: bool,
+    pub install_timeout_minutes: u64,
+}
+
+impl Default for ReconcileConfig {
+    fn default() -> Self {
+        Self {
+            brewfile: None,
+            overlays: HashMap::new(),
+            report_only: false,
+            uninstall_unlisted_leaves: false,
+            install_timeout_minutes: 60,
+        }
+    }
+}
+
+impl ReconcileConfig {
+    /// Content of the shared Brewfile followed by the overlay of the host, if any
+    pub fn desired_brewfile(&self, host_name: Option<&str>) -> Result<Option<String>> {
+        let Some(brewfile) = &self.brewfile else {
+            return Ok(None);
+        };
+        let mut content = fs::read_to_string(brewfile).with_context(|| format!("cannot read Brewfile {}", brewfile.display()))?;
+        if let Some(overlay) = host_name.and_then(|host_name| self.overlays.get(host_name)) {
+            let overlay_content =
+                fs::read_to_string(overlay).with_context(|| format!("cannot read Brewfile overlay {}", overlay.display()))?;
+            content.push_str(&format!("\n# overlay {}\n{}", overlay.display(), overlay_content));
+        }
+        Ok(Some(content))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DriftConfig {
+    /// Lockfile or directory of fleet lockfiles compared by `drift` when none is given
+    pub reference: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AutoremoveConfig {
+    /// Uninstall the dependencies no installed formula needs anymore
+    pub enabled: bool,
+    /// Formulae never autoremoved
+    pub protect: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CleanupConfig {
+    /// `brew cleanup --prune`: days of cache kept, or `all`
+    pub prune: Option<String>,
+    /// `brew cleanup -s`: also scrub the downloads of the latest versions
+    pub scrub: bool,
+    /// Number of previous versions kept for selected formulae
+    pub keep_versions: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DiskSpaceConfig {
+    /// Check that the planned upgrades fit on the prefix and cache volumes before upgrading
+    pub check: bool,
+    /// Space left free on top of the estimate
+    pub margin_mb: u64,
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        Self { check: true, margin_mb: 1024 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Run `brew doctor` and `brew config` before anything else
+    pub check: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self { check: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeprecationConfig {
+    /// Replace deprecated or disabled packages by the replacement Homebrew declares for them
+    pub migrate: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TapConfig {
+    /// Audit the installed taps after upgrading
+    pub audit: bool,
+    /// Third-party taps expected on this machine, by name or `owner/*`; Homebrew's own taps are always trusted
+    pub trusted: Vec<String>,
+    /// Untap the third-party taps no installed package comes from
+    pub untap_unused: bool,
+}
+
+impl Default for TapConfig {
+    fn default() -> Self {
+        Self { audit: true, trusted: vec![], untap_unused: false }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PinRule {
+    /// Last day the formula stays pinned, pinned for good when absent
+    pub until: Option<NaiveDate>,
+}
+
+impl PinRule {
+    /// Moment the pin expires: the end of its last day, in UTC
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.until.map(|until| (until + Duration::days(1)).and_time(NaiveTime::MIN).and_utc())
+    }
+}
+
+impl TimeoutConfig {
+    /// Timeout for upgrading a package: the explicit override if any, otherwise the slowest
+    /// recorded upgrade scaled by the multiplier and clamped between floor and ceiling
+    pub fn timeout_for(&self, package_name: &str, history: &History) -> Duration {
+        if let Some(minutes) = self.overrides.get(package_name) {
+            return Duration::minutes(*minutes as i64);
+        }
+        let seconds = match history.slowest_duration(package_name) {
+            Some(slowest) => (slowest as f64 * self.multiplier).ceil() as u64,
+            None => self.default_minutes * 60,
+        };
+        let floor = self.floor_minutes * 60;
+        let ceiling = (self.ceiling_minutes * 60).max(floor);
+        Duration::seconds(seconds.clamp(floor, ceiling) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_use_default_timeout_when_package_has_no_history() {
+        let config = TimeoutConfig::default();
+        assert_eq!(config.timeout_for("wget", &History::default()), Duration::minutes(5));
+    }
+
+    #[test]
+    fn should_scale_slowest_recorded_duration_within_bounds() {
+        let config = TimeoutConfig::default();
+        let mut history = History::default();
+        history.record_duration("qt", 1800);
+        history.record_duration("qt", 2400);
+        history.record_duration("jq", 3);
+        history.record_duration("llvm", 7200);
+        assert_eq!(config.timeout_for("qt", &history), Duration::minutes(120));
+        assert_eq!(config.timeout_for("jq", &history), Duration::minutes(2));
+        assert_eq!(config.timeout_for("llvm", &history), Duration::minutes(240));
+    }
+
+    #[test]
+    fn should_prefer_override_over_history() {
+        let config = TimeoutConfig { overrides: HashMap::from([("llvm".to_string(), 600)]), ..TimeoutConfig::default() };
+        let mut history = History::default();
+        history.record_duration("llvm", 60);
+        assert_eq!(config.timeout_for("llvm", &history), Duration::minutes(600));
+    }
+}
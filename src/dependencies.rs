@@ -0,0 +1,163 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{brew_command::PackageKind, formulae::Package, info::InstalledInfo};
+
+/// Dependency graph of the installed formulae, keyed by formula name
+pub struct DependencyGraph {
+    dependencies: HashMap<String, Vec<String>>,
+}
+
+impl DependencyGraph {
+    pub fn from_info(info: &InstalledInfo) -> Self {
+        let dependencies = info
+            .formulae
+            .iter()
+            .map(|f| {
+                let deps =
+                    f.dependencies.iter().map(|d| info.formula(d).map(|dep| dep.name.clone()).unwrap_or(d.clone())).collect();
+                (f.name.clone(), deps)
+            })
+            .collect();
+        Self { dependencies }
+    }
+
+    /// Every formula `name` depends on, directly or through other installed formulae
+    fn transitive_dependencies(&self, name: &str) -> HashSet<&str> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![name];
+        while let Some(current) = stack.pop() {
+            for dep in self.dependencies.get(current).into_iter().flatten() {
+                if seen.insert(dep.as_str()) {
+                    stack.push(dep.as_str());
+                }
+            }
+        }
+        seen
+    }
+
+    /// Every formula one of `names` depends on, directly or through other installed formulae
+    pub fn dependencies_of(&self, names: &[String]) -> HashSet<&str> {
+        names.iter().flat_map(|name| self.transitive_dependencies(name)).collect()
+    }
+
+    /// Orders the formulae so that dependents come before their dependencies, the order brew uninstalls them in.
+    /// Ties keep the original order
+    pub fn removal_order(&self, mut names: Vec<String>) -> Vec<String> {
+        let mut ordered = Vec::with_capacity(names.len());
+        while !names.is_empty() {
+            let needed_by_remaining = |name: &String| {
+                names.iter().any(|other| other != name && self.transitive_dependencies(other).contains(name.as_str()))
+            };
+            // a dependency cycle keeps the original order
+            let next = names.iter().position(|name| !needed_by_remaining(name)).unwrap_or(0);
+            ordered.push(names.remove(next));
+        }
+        ordered
+    }
+
+    /// Formulae among `packages` that each of them depends on, directly or not; casks and unknown formulae are left out
+    pub fn dependencies_among<'a>(&self, packages: &[Package<'a>]) -> HashMap<&'a str, HashSet<&'a str>> {
+        let formulae: Vec<&'a str> = packages
+            .iter()
+            .filter(|p| p.kind() == PackageKind::Formula && self.dependencies.contains_key(p.name()))
+            .map(|p| p.name())
+            .collect();
+        formulae
+            .iter()
+            .map(|&name| {
+                let deps = self.transitive_dependencies(name);
+                (name, formulae.iter().copied().filter(|other| *other != name && deps.contains(other)).collect())
+            })
+            .collect()
+    }
+
+    /// Orders the packages so that dependencies are upgraded before their dependents.
+    /// Ties keep the original order; casks and formulae unknown to the graph keep their relative position at the end.
+    /// Only formulae are graphed, so a cask sharing the name of a formula (e.g. `docker`) cannot collide with it
+    pub fn upgrade_order<'a>(&self, packages: &[Package<'a>]) -> Vec<Package<'a>> {
+        let (known, unknown): (Vec<Package<'a>>, Vec<Package<'a>>) =
+            packages.iter().partition(|p| p.kind() == PackageKind::Formula && self.dependencies.contains_key(p.name()));
+
+        let mut pending = self.dependencies_among(&known);
+
+        let position: HashMap<&str, usize> = known.iter().enumerate().map(|(i, p)| (p.name(), i)).collect();
+        let mut ready: BTreeSet<usize> =
+            pending.iter().filter(|(_, deps)| deps.is_empty()).map(|(name, _)| position[name]).collect();
+        let mut ordered = Vec::with_capacity(packages.len());
+        while let Some(index) = ready.pop_first() {
+            let package = known[index];
+            pending.remove(package.name());
+            ordered.push(package);
+            for (name, deps) in pending.iter_mut() {
+                if deps.remove(package.name()) && deps.is_empty() {
+                    ready.insert(position[name]);
+                }
+            }
+        }
+
+        // Dependency cycles cannot be ordered: keep them in their original order
+        let mut cyclic: Vec<Package<'a>> = pending.keys().map(|name| known[position[name]]).collect();
+        cyclic.sort_by_key(|p| position[p.name()]);
+        ordered.extend(cyclic);
+        ordered.extend(unknown);
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formulae::{Cask, Formula};
+
+    fn formula(name: &str) -> Formula {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "installed_versions": ["1"], "current_version": "2", "pinned": false
+        }))
+        .unwrap()
+    }
+
+    fn info() -> InstalledInfo {
+        serde_json::from_value(serde_json::json!({
+            "formulae": [
+                {"name": "ca-certificates", "full_name": "ca-certificates", "dependencies": []},
+                {"name": "openssl@3", "full_name": "openssl@3", "dependencies": ["ca-certificates"]},
+                {"name": "python@3.13", "full_name": "python@3.13", "dependencies": ["openssl@3", "sqlite"]},
+                {"name": "sqlite", "full_name": "sqlite", "dependencies": []},
+                {"name": "awscli", "full_name": "awscli", "dependencies": ["python@3.13"]},
+                {"name": "wget", "full_name": "wget", "dependencies": ["openssl@3"]}
+            ],
+            "casks": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_upgrade_dependencies_before_dependents() {
+        let formulae = [formula("awscli"), formula("ca-certificates"), formula("jq-cask"), formula("wget"), formula("sqlite")];
+        let refs: Vec<Package> = formulae.iter().map(Package::Formula).collect();
+        let graph = DependencyGraph::from_info(&info());
+        let order: Vec<&str> = graph.upgrade_order(&refs).iter().map(|p| p.name()).collect();
+        assert_eq!(order, vec!["ca-certificates", "wget", "sqlite", "awscli", "jq-cask"]);
+    }
+
+    #[test]
+    fn should_remove_dependents_before_their_dependencies() {
+        let graph = DependencyGraph::from_info(&info());
+        let names = ["ca-certificates", "openssl@3", "jq", "wget"].map(String::from).to_vec();
+        assert_eq!(graph.removal_order(names), vec!["jq", "wget", "openssl@3", "ca-certificates"]);
+    }
+
+    #[test]
+    fn should_keep_cask_sharing_the_name_of_a_formula() {
+        let formulae = [formula("wget"), formula("openssl@3")];
+        let cask: Cask = serde_json::from_value(serde_json::json!({
+            "name": "wget", "installed_versions": ["1"], "current_version": "2"
+        }))
+        .unwrap();
+        let mut packages: Vec<Package> = vec![Package::Cask(&cask)];
+        packages.extend(formulae.iter().map(Package::Formula));
+        let graph = DependencyGraph::from_info(&info());
+        let order: Vec<_> = graph.upgrade_order(&packages).iter().map(|p| p.key()).collect();
+        assert_eq!(order, vec![(PackageKind::Formula, "openssl@3"), (PackageKind::Formula, "wget"), (PackageKind::Cask, "wget")]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt::Display;
+
+use crate::{
+    brew_command::PackageKind,
+    info::{InstalledInfo, Lifecycle},
+};
+
+/// An installed package Homebrew deprecated or disabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deprecation {
+    pub name: String,
+    pub kind: PackageKind,
+    /// Disabled packages can no longer be installed nor upgraded, deprecated ones will be disabled
+    pub disabled: bool,
+    pub date: Option<String>,
+    pub reason: Option<String>,
+    /// Package declared as its replacement
+    pub replacement: Option<(String, PackageKind)>,
+}
+
+/// Installed formulae and casks that are deprecated or disabled
+pub fn find(info: &InstalledInfo) -> Vec<Deprecation> {
+    let formulae = info.formulae.iter().map(|f| (&f.name, PackageKind::Formula, &f.lifecycle));
+    let casks = info.casks.iter().map(|c| (&c.token, PackageKind::Cask, &c.lifecycle));
+    formulae.chain(casks).filter_map(|(name, kind, lifecycle)| deprecation(name, kind, lifecycle)).collect()
+}
+
+fn deprecation(name: &str, kind: PackageKind, lifecycle: &Lifecycle) -> Option<Deprecation> {
+    let (date, reason, formula, cask) = if lifecycle.disabled {
+        (
+            &lifecycle.disable_date,
+            &lifecycle.disable_reason,
+            &lifecycle.disable_replacement_formula,
+            &lifecycle.disable_replacement_cask,
+        )
+    } else if lifecycle.deprecated {
+        (
+            &lifecycle.deprecation_date,
+            &lifecycle.deprecation_reason,
+            &lifecycle.deprecation_replacement_formula,
+            &lifecycle.deprecation_replacement_cask,
+        )
+    } else {
+        return None;
+    };
+    let replacement = match (formula, cask) {
+        (Some(formula), _) => Some((formula.clone(), PackageKind::Formula)),
+        (None, Some(cask)) => Some((cask.clone(), PackageKind::Cask)),
+        (None, None) => None,
+    };
+    Some(Deprecation {
+        name: name.to_string(),
+        kind,
+        disabled: lifecycle.disabled,
+        date: date.clone(),
+        reason: reason.clone(),
+        replacement,
+    })
+}
+
+impl Display for Deprecation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.kind == PackageKind::Cask { " (cask)" } else { "" };
+        write!(f, "\t - {}{} => {}", self.name, kind, if self.disabled { "disabled" } else { "deprecated" })?;
+        if let Some(date) = &self.date {
+            write!(f, " since {}", date)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        if let Some((replacement, _)) = &self.replacement {
+            write!(f, ", replaced by {}", replacement)?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_list_deprecated_and_disabled_packages_with_replacements() {
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{
+                "formulae": [
+                    {"name": "jq"},
+                    {"name": "youtube-dl", "deprecated": true, "deprecation_date": "2024-10-05",
+                     "deprecation_reason": "unmaintained", "deprecation_replacement_formula": "yt-dlp"},
+                    {"name": "python@3.9", "deprecated": true, "disabled": true, "disable_date": "2025-10-05",
+                     "disable_reason": "versioned_formula", "deprecation_date": "2024-10-05"}
+                ],
+                "casks": [{"token": "atom", "deprecated": true, "deprecation_reason": "discontinued",
+                           "deprecation_replacement_cask": "zed"}]
+            }"#,
+        )
+        .unwrap();
+        let deprecations = find(&info);
+        assert_eq!(deprecations.len(), 3);
+        assert_eq!(deprecations[0].replacement, Some(("yt-dlp".to_string(), PackageKind::Formula)));
+        assert!(deprecations[1].disabled);
+        assert_eq!(deprecations[1].date.as_deref(), Some("2025-10-05"));
+        assert_eq!(deprecations[2].kind, PackageKind::Cask);
+        assert_eq!(
+            deprecations[0].to_string(),
+            "\t - youtube-dl => deprecated since 2024-10-05: unmaintained, replaced by yt-dlp\n"
+        );
+        assert_eq!(deprecations[2].replacement, Some(("zed".to_string(), PackageKind::Cask)));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs, io, path::Path};
+
+/// Space available to unprivileged users on the volume holding the path
+pub fn free_bytes(path: &Path) -> io::Result<u64> {
+    let stats = nix::sys::statvfs::statvfs(path).map_err(io::Error::from)?;
+    Ok(stats.blocks_available() as u64 * stats.fragment_size() as u64)
+}
+
+pub fn same_volume(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev(),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        // without device ids the volumes are assumed distinct, checking each one
+        let _ = (a, b);
+        false
+    }
+}
+
+/// Disk usage of the files under the path, without following symlinks
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path).map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum()).unwrap_or_default()
+}
+
+/// Splits the packages into those fitting in the available space and those left out, leaving out the largest first
+pub fn fit<K: Copy + PartialEq>(estimates: &[(K, u64)], available: u64) -> (Vec<K>, Vec<K>) {
+    let mut by_size: Vec<&(K, u64)> = estimates.iter().collect();
+    by_size.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    let mut needed: u64 = estimates.iter().map(|(_, size)| size).sum();
+    let mut left_out = vec![];
+    for (name, size) in by_size {
+        if needed <= available {
+            break;
+        }
+        needed -= size;
+        left_out.push(*name);
+    }
+    let fitting = estimates.iter().map(|(name, _)| *name).filter(|name| !left_out.contains(name)).collect();
+    (fitting, left_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_leave_out_largest_packages_until_the_rest_fits() {
+        let estimates = [("jq", 2), ("llvm", 1500), ("node", 80), ("qt", 900)];
+        assert_eq!(fit(&estimates, 3000), (vec!["jq", "llvm", "node", "qt"], vec![]));
+        assert_eq!(fit(&estimates, 1000), (vec!["jq", "node", "qt"], vec!["llvm"]));
+        assert_eq!(fit(&estimates, 50), (vec!["jq"], vec!["llvm", "qt", "node"]));
+    }
+
+    #[test]
+    fn should_measure_directory_sizes_and_free_space() {
+        let dir = std::env::temp_dir().join(format!("brew-maintainer-disk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join("bin/tool"), vec![0u8; 1000]).unwrap();
+        fs::write(dir.join("README"), vec![0u8; 24]).unwrap();
+        assert_eq!(dir_size(&dir), 1024);
+        assert!(free_bytes(&dir).unwrap() > 0);
+        assert!(same_volume(&dir, &dir.join("bin")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
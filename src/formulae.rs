@@ -2,22 +2,35 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+use crate::brew_command::PackageKind;
+
+/// Output of `brew outdated --json=v2`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct OutdatedPackages {
-    pub formulae: Vec<Package>,
-    pub casks: Vec<Package>,
+    #[serde(default)]
+    pub formulae: Vec<Formula>,
+    #[serde(default)]
+    pub casks: Vec<Cask>,
 }
 
 impl OutdatedPackages {
-    pub fn iter(&self) -> impl Iterator<Item = &Package> {
-        self.formulae.iter().chain(self.casks.iter())
+    pub fn iter(&self) -> impl Iterator<Item = Package<'_>> {
+        self.formulae.iter().map(Package::Formula).chain(self.casks.iter().map(Package::Cask))
+    }
+
+    /// Adds the casks not already listed
+    pub fn merge_casks(&mut self, casks: impl IntoIterator<Item = Cask>) {
+        for cask in casks {
+            if !self.casks.iter().any(|c| c.name == cask.name) {
+                self.casks.push(cask);
+            }
+        }
     }
 }
 
 impl From<&OutdatedPackages> for String {
     fn from(output: &OutdatedPackages) -> Self {
-        let formulae_str = output.formulae.iter().map(|p| format!("{}", p)).collect();
-        formulae_str
+        output.iter().map(|p| format!("{}", p)).collect()
     }
 }
 impl Display for OutdatedPackages {
@@ -26,17 +39,58 @@ impl Display for OutdatedPackages {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Package {
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Formula {
     pub name: String,
-    installed_versions: Vec<String>,
-    current_version: String,
-    pinned: bool,
+    pub installed_versions: Vec<String>,
+    pub current_version: String,
+    #[serde(default)]
+    pub pinned: bool,
     #[serde(default)]
-    pinned_version: Option<String>,
+    pub pinned_version: Option<String>,
+}
+
+/// Casks cannot be pinned and their versions are cask version strings (e.g. `1.2.3,abcdef` or `latest`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cask {
+    pub name: String,
+    pub installed_versions: Vec<String>,
+    pub current_version: String,
+}
+
+/// An outdated formula or cask
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Package<'a> {
+    Formula(&'a Formula),
+    Cask(&'a Cask),
 }
 
-impl Display for Package {
+impl<'a> Package<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            Package::Formula(formula) => &formula.name,
+            Package::Cask(cask) => &cask.name,
+        }
+    }
+
+    /// Identifies the package even when a formula and a cask share its name
+    pub fn key(&self) -> (PackageKind, &'a str) {
+        (self.kind(), self.name())
+    }
+
+    pub fn is_cask(&self) -> bool {
+        matches!(self, Package::Cask(_))
+    }
+
+    pub fn kind(&self) -> PackageKind {
+        match self {
+            Package::Formula(_) => PackageKind::Formula,
+            Package::Cask(_) => PackageKind::Cask,
+        }
+    }
+}
+
+impl Display for Formula {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
@@ -49,3 +103,74 @@ impl Display for Package {
         )
     }
 }
+
+impl Display for Cask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "\t - {} (cask) => available: {} | installed: {}",
+            &self.name,
+            &self.current_version,
+            &self.installed_versions.join(", ")
+        )
+    }
+}
+
+impl Display for Package<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Package::Formula(formula) => formula.fmt(f),
+            Package::Cask(cask) => cask.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTDATED_V2: &str = include_str!("../testdata/brew_outdated_v2.json");
+
+    #[test]
+    fn should_parse_formulae_and_casks_from_brew_outdated_v2() {
+        let outdated: OutdatedPackages = serde_json::from_str(OUTDATED_V2).unwrap();
+        assert_eq!(outdated.formulae.len(), 3);
+        assert_eq!(
+            outdated.formulae[1],
+            Formula {
+                name: "node@20".to_string(),
+                installed_versions: vec!["20.18.0".to_string(), "20.18.1".to_string()],
+                current_version: "20.19.5".to_string(),
+                pinned: true,
+                pinned_version: Some("20.18.1".to_string()),
+            }
+        );
+        assert_eq!(
+            outdated.casks[0],
+            Cask {
+                name: "firefox".to_string(),
+                installed_versions: vec!["143.0.1".to_string()],
+                current_version: "144.0".to_string(),
+            }
+        );
+        assert_eq!(outdated.iter().filter(|p| p.is_cask()).map(|p| p.name()).collect::<Vec<_>>(), vec!["firefox", "docker"]);
+    }
+
+    #[test]
+    fn should_display_casks_along_formulae() {
+        let outdated: OutdatedPackages = serde_json::from_str(OUTDATED_V2).unwrap();
+        let display = outdated.to_string();
+        assert_eq!(display.lines().count(), 5);
+        assert!(display.contains("\t - docker (cask) => available: 4.48.0,207573 | installed: 4.47.0,206054"));
+    }
+
+    #[test]
+    fn should_tolerate_unknown_fields_and_missing_sections() {
+        let outdated: OutdatedPackages = serde_json::from_str(
+            r#"{"formulae": [{"name": "jq", "installed_versions": ["1.7.1"], "current_version": "1.8.0", "future": 1}]}"#,
+        )
+        .unwrap();
+        assert!(!outdated.formulae[0].pinned);
+        assert!(outdated.casks.is_empty());
+    }
+}
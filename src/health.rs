@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+/// A `Warning:` paragraph of `brew doctor`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoctorWarning {
+    pub title: String,
+    pub detail: String,
+}
+
+/// Effect of a problem on the run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Impact {
+    /// Upgrades cannot succeed, the run is aborted
+    Blocking,
+    /// Taps cannot be updated, the run upgrades with the current metadata
+    NoUpdate,
+    /// Nothing can be compiled, formulae without a bottle are not upgraded
+    NoSourceBuilds,
+    None,
+}
+
+impl DoctorWarning {
+    /// Impact of the warning on a brew installed in `prefix`, unwritable directories only block when brew installs into them
+    pub fn impact(&self, prefix: &Path) -> Impact {
+        let title = self.title.to_lowercase();
+        let blocks_installs =
+            |path: &Path| path == prefix || path.starts_with(prefix.join("Cellar")) || path.starts_with(prefix.join("Caskroom"));
+        if title.contains("not writable") && self.paths().any(blocks_installs) {
+            Impact::Blocking
+        } else if (title.contains("command line tools") || title.contains("xcode"))
+            && (title.contains("outdated") || title.contains("too old") || title.contains("missing"))
+        {
+            Impact::NoSourceBuilds
+        } else if title.contains("git")
+            && ["could not be found", "not a git repository", "broken", "corrupt"].iter().any(|p| title.contains(p))
+        {
+            Impact::NoUpdate
+        } else {
+            Impact::None
+        }
+    }
+
+    /// Paths listed in the detail of the warning
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.detail.lines().map(str::trim).filter(|line| line.starts_with('/')).map(Path::new)
+    }
+}
+
+/// Warnings printed by `brew doctor`, empty when the system is ready to brew
+pub fn doctor_warnings(output: &str) -> Vec<DoctorWarning> {
+    let mut warnings: Vec<DoctorWarning> = vec![];
+    for line in output.lines() {
+        if let Some(title) = line.strip_prefix("Warning: ") {
+            warnings.push(DoctorWarning { title: title.trim().to_string(), detail: String::new() });
+        } else if let Some(warning) = warnings.last_mut() {
+            warning.detail.push_str(line);
+            warning.detail.push('\n');
+        }
+    }
+    for warning in &mut warnings {
+        warning.detail = warning.detail.trim().to_string();
+    }
+    warnings
+}
+
+/// `key: value` lines of `brew config`
+pub fn brew_config(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Problems visible in `brew config` alone: a missing git or, on macOS, missing Command Line Tools
+pub fn config_impacts(config: &BTreeMap<String, String>) -> Vec<(Impact, String)> {
+    let mut impacts = vec![];
+    if config.get("Git").is_some_and(|git| git.starts_with("N/A")) {
+        impacts.push((Impact::NoUpdate, "git is not installed".to_string()));
+    }
+    if config.get("CLT").is_some_and(|clt| clt.starts_with("N/A"))
+        && config.get("Xcode").is_none_or(|xcode| xcode.starts_with("N/A"))
+    {
+        impacts.push((Impact::NoSourceBuilds, "neither the Command Line Tools nor Xcode are installed".to_string()));
+    }
+    impacts
+}
+
+impl Display for DoctorWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "\t - {}", self.title)?;
+        for line in self.detail.lines() {
+            writeln!(f, "\t\t{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCTOR: &str = "Please note that these warnings are just used to help the Homebrew maintainers
+with debugging if you file an issue.
+
+Warning: The following directories are not writable by your user:
+/opt/homebrew/share/man/man8
+
+You should change the ownership of these directories to your user.
+
+Warning: Your Command Line Tools are too outdated.
+Update them from Software Update in System Settings.
+
+Warning: Some installed formulae are deprecated or disabled.
+";
+
+    #[test]
+    fn should_parse_doctor_warnings_and_classify_their_impact() {
+        let warnings = doctor_warnings(DOCTOR);
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(warnings[0].title, "The following directories are not writable by your user:");
+        assert!(warnings[0].detail.starts_with("/opt/homebrew/share/man/man8\n\nYou should change"));
+        let impacts: Vec<Impact> = warnings.iter().map(|w| w.impact(Path::new("/opt/homebrew"))).collect();
+        assert_eq!(impacts, vec![Impact::None, Impact::NoSourceBuilds, Impact::None]);
+        assert!(doctor_warnings("Your system is ready to brew.\n").is_empty());
+    }
+
+    #[test]
+    fn should_only_block_on_unwritable_prefix_cellar_or_caskroom() {
+        let unwritable = |paths: &str| DoctorWarning {
+            title: "The following directories are not writable by your user:".to_string(),
+            detail: paths.to_string(),
+        };
+        let prefix = Path::new("/opt/homebrew");
+        assert_eq!(unwritable("/opt/homebrew/share/man/man8\n/opt/homebrew/Cellarium").impact(prefix), Impact::None);
+        assert_eq!(unwritable("/opt/homebrew/share/man/man8\n/opt/homebrew/Cellar").impact(prefix), Impact::Blocking);
+        assert_eq!(unwritable("/opt/homebrew/Caskroom/firefox").impact(prefix), Impact::Blocking);
+        assert_eq!(unwritable("/opt/homebrew").impact(prefix), Impact::Blocking);
+    }
+
+    #[test]
+    fn should_detect_missing_git_and_toolchain_in_brew_config() {
+        let config = brew_config("HOMEBREW_VERSION: 4.6.17\nGit: N/A\nCLT: N/A\nXcode: N/A\nmacOS: 15.6.1-arm64\n");
+        assert_eq!(config.get("HOMEBREW_VERSION").map(String::as_str), Some("4.6.17"));
+        let impacts: Vec<Impact> = config_impacts(&config).into_iter().map(|(impact, _)| impact).collect();
+        assert_eq!(impacts, vec![Impact::NoUpdate, Impact::NoSourceBuilds]);
+        assert!(config_impacts(&brew_config("Git: 2.50.1 => /opt/homebrew/bin/git\nCLT: 16.4.0.0.1\n")).is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Number of upgrade durations kept per package
+const MAX_DURATION_SAMPLES: usize = 5;
+/// Number of autoremoved formulae remembered
+const MAX_AUTOREMOVED: usize = 200;
+
+/// State persisted between runs
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct History {
+    /// Most recent successful upgrade durations per package, in seconds
+    pub durations: HashMap<String, Vec<u64>>,
+    /// Kegs replaced by an upgrade and kept installed so that the upgrade can be rolled back
+    pub retained_kegs: Vec<RetainedKeg>,
+    pub rollbacks: Vec<RollbackRecord>,
+    /// Formulae uninstalled by the autoremove phase, oldest first, recorded to find out what to reinstall by hand
+    pub autoremoved: Vec<AutoremovedFormula>,
+    /// Disk space freed by all cleanups
+    pub reclaimed_bytes: u64,
+    /// Titles of the `brew doctor` warnings of the last run
+    pub doctor_warnings: Vec<String>,
+    /// Pins made by the maintainer, the only ones it unpins when they expire
+    pub pins: Vec<PinRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RetainedKeg {
+    pub name: String,
+    pub version: String,
+    pub upgraded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AutoremovedFormula {
+    pub name: String,
+    pub version: Option<String>,
+    pub removed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RollbackRecord {
+    pub name: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub rolled_back_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PinRecord {
+    pub name: String,
+    pub pinned_at: DateTime<Utc>,
+    /// Kept pinned for good when absent
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Declared in the configuration, released once it is not anymore
+    #[serde(default)]
+    pub configured: bool,
+}
+
+impl History {
+    pub fn load(path: &Path) -> Result<History> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).with_context(|| format!("invalid history in {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(History::default()),
+            Err(e) => Err(e).with_context(|| format!("cannot read history {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("cannot create {}", parent.display()))?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content).with_context(|| format!("cannot write history {}", path.display()))
+    }
+
+    pub fn record_duration(&mut self, package_name: &str, seconds: u64) {
+        let samples = self.durations.entry(package_name.to_string()).or_default();
+        samples.push(seconds);
+        if samples.len() > MAX_DURATION_SAMPLES {
+            samples.remove(0);
+        }
+    }
+
+    pub fn slowest_duration(&self, package_name: &str) -> Option<u64> {
+        self.durations.get(package_name).and_then(|samples| samples.iter().max().copied())
+    }
+
+    /// Remembers the keg replaced by an upgrade, superseding any older keg of the same formula
+    pub fn retain_keg(&mut self, name: &str, version: &str, upgraded_at: DateTime<Utc>) {
+        self.retained_kegs.retain(|keg| keg.name != name);
+        self.retained_kegs.push(RetainedKeg { name: name.to_string(), version: version.to_string(), upgraded_at });
+    }
+
+    /// Forgets the kegs retained for longer than the retention period
+    pub fn expire_retained_kegs(&mut self, retention_days: u64, now: DateTime<Utc>) {
+        let retention = Duration::days(retention_days as i64);
+        self.retained_kegs.retain(|keg| now - keg.upgraded_at < retention);
+    }
+
+    pub fn record_autoremoved(&mut self, name: &str, version: Option<String>, removed_at: DateTime<Utc>) {
+        self.autoremoved.push(AutoremovedFormula { name: name.to_string(), version, removed_at });
+        let excess = self.autoremoved.len().saturating_sub(MAX_AUTOREMOVED);
+        self.autoremoved.drain(..excess);
+    }
+
+    /// Remembers a pin, superseding an older pin of the same formula but keeping when it was first pinned
+    pub fn record_pin(&mut self, mut record: PinRecord) {
+        if let Some(pin) = self.pin(&record.name) {
+            record.pinned_at = pin.pinned_at;
+        }
+        self.pins.retain(|pin| pin.name != record.name);
+        self.pins.push(record);
+    }
+
+    pub fn forget_pin(&mut self, name: &str) {
+        self.pins.retain(|pin| pin.name != name);
+    }
+
+    pub fn pin(&self, name: &str) -> Option<&PinRecord> {
+        self.pins.iter().find(|pin| pin.name == name)
+    }
+
+    /// Names of the recorded pins expired at `now`
+    pub fn expired_pins(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.pins
+            .iter()
+            .filter(|pin| pin.expires_at.is_some_and(|expires_at| expires_at <= now))
+            .map(|pin| pin.name.clone())
+            .collect()
+    }
+
+    pub fn retained_keg(&self, name: &str) -> Option<&RetainedKeg> {
+        self.retained_kegs.iter().find(|keg| keg.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_latest_retained_keg_per_formula_until_it_expires() {
+        let mut history = History::default();
+        let now = Utc::now();
+        history.retain_keg("jq", "1.7", now - Duration::days(20));
+        history.retain_keg("jq", "1.7.1", now - Duration::days(2));
+        history.retain_keg("wget", "1.24.5", now - Duration::days(9));
+        history.expire_retained_kegs(7, now);
+        assert_eq!(history.retained_kegs.len(), 1);
+        assert_eq!(history.retained_keg("jq").map(|keg| keg.version.as_str()), Some("1.7.1"));
+        assert_eq!(history.retained_keg("wget"), None);
+    }
+
+    #[test]
+    fn should_keep_first_pin_date_and_latest_expiry() {
+        let mut history = History::default();
+        let now = Utc::now();
+        let pin = |name: &str, pinned_at, expires_at| PinRecord { name: name.to_string(), pinned_at, expires_at, configured: true };
+        history.record_pin(pin("node", now - Duration::days(10), Some(now - Duration::days(1))));
+        history.record_pin(pin("jq", now, None));
+        assert_eq!(history.expired_pins(now), vec!["node"]);
+        history.record_pin(pin("node", now, Some(now + Duration::days(3))));
+        assert!(history.expired_pins(now).is_empty());
+        assert_eq!(history.pin("node").map(|pin| pin.pinned_at), Some(now - Duration::days(10)));
+        assert_eq!(history.pins.len(), 2);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::platform;
+
+/// Output of `brew info --json=v2 --installed`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InstalledInfo {
+    #[serde(default)]
+    pub formulae: Vec<FormulaInfo>,
+    #[serde(default)]
+    pub casks: Vec<CaskInfo>,
+}
+
+impl InstalledInfo {
+    pub fn formula(&self, name: &str) -> Option<&FormulaInfo> {
+        self.formulae.iter().find(|f| f.name == name || f.full_name == name)
+    }
+
+    pub fn cask(&self, token: &str) -> Option<&CaskInfo> {
+        self.casks.iter().find(|c| c.token == token || c.full_token == token)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FormulaInfo {
+    pub name: String,
+    pub full_name: String,
+    pub tap: Option<String>,
+    pub revision: u32,
+    pub dependencies: Vec<String>,
+    #[serde(flatten)]
+    pub lifecycle: Lifecycle,
+    pub installed: Vec<InstalledKeg>,
+    /// Version of the keg linked into the prefix
+    pub linked_keg: Option<String>,
+    /// Bottle specifications keyed by spec name (`stable`)
+    pub bottle: HashMap<String, BottleSpec>,
+}
+
+impl FormulaInfo {
+    /// Linked version, or the most recent installed one when the formula is not linked
+    pub fn installed_version(&self) -> Option<String> {
+        self.linked_keg.clone().or_else(|| self.installed.last().map(|keg| keg.version.clone()))
+    }
+
+    /// Whether brew can pour a bottle of the stable version for the given bottle tag (e.g. `arm64_sequoia`)
+    pub fn has_bottle_for(&self, tag: &str) -> bool {
+        self.bottle_for(tag).is_some()
+    }
+
+    /// Bottle of the stable version brew pours for the given bottle tag, one built for an older macOS release when
+    /// there is none for this one
+    pub fn bottle_for(&self, tag: &str) -> Option<&BottleFile> {
+        let spec = self.bottle.get("stable")?;
+        platform::compatible_bottle_tags(tag).iter().find_map(|tag| spec.files.get(tag))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BottleSpec {
+    pub rebuild: u32,
+    pub root_url: String,
+    /// Bottle files keyed by bottle tag
+    pub files: HashMap<String, BottleFile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BottleFile {
+    pub cellar: String,
+    pub url: String,
+    pub sha256: String,
+    /// Size of the bottle archive, when brew reports it
+    pub size: Option<u64>,
+}
+
+/// Deprecation and disable details of a formula or cask
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Lifecycle {
+    pub deprecated: bool,
+    pub deprecation_date: Option<String>,
+    pub deprecation_reason: Option<String>,
+    pub deprecation_replacement_formula: Option<String>,
+    pub deprecation_replacement_cask: Option<String>,
+    pub disabled: bool,
+    pub disable_date: Option<String>,
+    pub disable_reason: Option<String>,
+    pub disable_replacement_formula: Option<String>,
+    pub disable_replacement_cask: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InstalledKeg {
+    pub version: String,
+    pub installed_as_dependency: bool,
+    pub installed_on_request: bool,
+    pub poured_from_bottle: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CaskInfo {
+    pub token: String,
+    pub full_token: String,
+    pub tap: Option<String>,
+    pub installed: Option<String>,
+    #[serde(flatten)]
+    pub lifecycle: Lifecycle,
+    /// Artifacts such as `{"app": ["Firefox.app"]}` or `{"binary": [...]}`
+    pub artifacts: Vec<serde_json::Value>,
+}
+
+impl CaskInfo {
+    /// Application bundles installed by the cask
+    pub fn apps(&self) -> Vec<String> {
+        self.artifacts
+            .iter()
+            .filter_map(|artifact| artifact.get("app")?.as_array())
+            .flatten()
+            .filter_map(|app| app.as_str().map(String::from))
+            .collect()
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::lockfile::compare_versions;
+
+fn opt_link(prefix: &Path, name: &str) -> PathBuf {
+    prefix.join("opt").join(name)
+}
+
+fn pin_link(prefix: &Path, name: &str) -> PathBuf {
+    prefix.join("var/homebrew/pinned").join(name)
+}
+
+/// Version of the keg the `opt` link of the formula points to
+pub fn linked_version(prefix: &Path, name: &str) -> Option<String> {
+    let target = fs::read_link(opt_link(prefix, name)).ok()?;
+    target.file_name().map(|version| version.to_string_lossy().to_string())
+}
+
+/// Versions of the formula present in the Cellar, most recent version first. Versions are compared rather than keg
+/// modification times, which anything touching a keg directory changes
+pub fn installed_versions(prefix: &Path, name: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(prefix.join("Cellar").join(name)) else {
+        return vec![];
+    };
+    let mut versions: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    versions.sort_by(|a, b| compare_versions(b, a));
+    versions
+}
+
+/// Points the `opt` link of the formula to another installed keg, which is the keg `brew link` then acts on
+pub fn point_opt_to(prefix: &Path, name: &str, version: &str) -> io::Result<()> {
+    replace_link(&opt_link(prefix, name), &Path::new("../Cellar").join(name).join(version))
+}
+
+/// Points the pin of a pinned formula to another installed keg: `brew pin` pins the most recent installed keg, whatever
+/// keg `opt` points to
+pub fn point_pin_to(prefix: &Path, name: &str, version: &str) -> io::Result<()> {
+    let link = pin_link(prefix, name);
+    if let Some(pinned) = link.parent() {
+        fs::create_dir_all(pinned)?;
+    }
+    replace_link(&link, &Path::new("../../../Cellar").join(name).join(version))
+}
+
+fn replace_link(link: &Path, target: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)?;
+    }
+    symlink(target, link)
+}
+
+/// Removes the kegs other than the linked one beyond the `keep` most recent, never removing the `spared` version nor
+/// any keg of a pinned formula, which brew keeps as well.
+/// Returns the outcome of the removal of each keg, a failure not preventing the removal of the others
+pub fn remove_previous_kegs(prefix: &Path, name: &str, keep: usize, spared: Option<&str>) -> Vec<(String, io::Result<()>)> {
+    if fs::symlink_metadata(pin_link(prefix, name)).is_ok() {
+        return vec![];
+    }
+    let linked = linked_version(prefix, name);
+    let previous = installed_versions(prefix, name).into_iter().filter(|version| Some(version) != linked.as_ref());
+    previous
+        .skip(keep)
+        .filter(|version| Some(version.as_str()) != spared)
+        .map(|version| {
+            let outcome = fs::remove_dir_all(prefix.join("Cellar").join(name).join(&version));
+            (version, outcome)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "kegs can only be relinked on unix"))
+}
@@ -0,0 +1,34 @@
+/// Libraries listed under the broken sections (`Broken dependencies:`, `Missing libraries:`) of `brew linkage` output.
+/// Falls back to the whole output when it has no such section
+pub fn broken_libraries(output: &str) -> Vec<String> {
+    let mut broken = vec![];
+    let mut in_broken_section = false;
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            in_broken_section = line.starts_with("Broken") || line.starts_with("Missing");
+        } else if in_broken_section && !line.trim().is_empty() {
+            broken.push(line.trim().to_string());
+        }
+    }
+    if broken.is_empty() && !output.trim().is_empty() {
+        broken.push(output.trim().to_string());
+    }
+    broken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_extract_broken_and_missing_libraries() {
+        let output = "System libraries:\n  /usr/lib/libSystem.B.dylib\n\
+                      Missing libraries:\n  /opt/homebrew/opt/icu4c/lib/libicuuc.74.dylib\n\
+                      Broken dependencies:\n  /opt/homebrew/opt/icu4c/lib/libicui18n.74.dylib (icu4c)\n";
+        assert_eq!(
+            broken_libraries(output),
+            vec!["/opt/homebrew/opt/icu4c/lib/libicuuc.74.dylib", "/opt/homebrew/opt/icu4c/lib/libicui18n.74.dylib (icu4c)"]
+        );
+        assert_eq!(broken_libraries("Error: No such keg"), vec!["Error: No such keg"]);
+    }
+}
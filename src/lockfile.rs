@@ -0,0 +1,260 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::info::InstalledInfo;
+
+/// Exact versions installed on a machine, written after every run
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Lockfile {
+    pub generated_at: DateTime<Utc>,
+    pub host: Option<String>,
+    pub formulae: BTreeMap<String, LockedFormula>,
+    pub casks: BTreeMap<String, LockedCask>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LockedFormula {
+    /// Installed version, including the `_<revision>` suffix brew appends to revised formulae
+    pub version: String,
+    pub revision: u32,
+    pub tap: Option<String>,
+    pub poured_from_bottle: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LockedCask {
+    pub version: String,
+    pub tap: Option<String>,
+}
+
+impl Lockfile {
+    pub fn new(info: &InstalledInfo, host: Option<String>, generated_at: DateTime<Utc>) -> Self {
+        let formulae = info
+            .formulae
+            .iter()
+            .filter_map(|f| {
+                let keg = match &f.linked_keg {
+                    Some(linked) => f.installed.iter().find(|keg| &keg.version == linked),
+                    None => f.installed.last(),
+                }?;
+                let locked = LockedFormula {
+                    version: keg.version.clone(),
+                    revision: f.revision,
+                    tap: f.tap.clone(),
+                    poured_from_bottle: keg.poured_from_bottle,
+                };
+                Some((f.name.clone(), locked))
+            })
+            .collect();
+        let casks = info
+            .casks
+            .iter()
+            .filter_map(|c| Some((c.token.clone(), LockedCask { version: c.installed.clone()?, tap: c.tap.clone() })))
+            .collect();
+        Self { generated_at, host, formulae, casks }
+    }
+
+    pub fn load(path: &Path) -> Result<Lockfile> {
+        let content = fs::read_to_string(path).with_context(|| format!("cannot read lockfile {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("invalid lockfile in {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("cannot create {}", parent.display()))?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content).with_context(|| format!("cannot write lockfile {}", path.display()))
+    }
+
+    fn versions(&self) -> impl Iterator<Item = (String, &str)> {
+        let formulae = self.formulae.iter().map(|(name, locked)| (name.clone(), locked.version.as_str()));
+        let casks = self.casks.iter().map(|(token, locked)| (format!("{} (cask)", token), locked.version.as_str()));
+        formulae.chain(casks)
+    }
+}
+
+/// Reference lockfiles: the given file, or every lockfile of a fleet directory except the one of this host
+pub fn references(path: &Path, host: Option<&str>) -> Result<Vec<(PathBuf, Lockfile)>> {
+    if !path.is_dir() {
+        return Ok(vec![(path.to_path_buf(), Lockfile::load(path)?)]);
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(path)
+        .with_context(|| format!("cannot read {}", path.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    let mut references = vec![];
+    for path in paths {
+        let lockfile = Lockfile::load(&path)?;
+        if host.is_none() || lockfile.host.as_deref() != host {
+            references.push((path, lockfile));
+        }
+    }
+    Ok(references)
+}
+
+/// Packages whose local version differs from a reference lockfile
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Drift {
+    /// Package, local version and reference version
+    pub ahead: Vec<(String, String, String)>,
+    pub behind: Vec<(String, String, String)>,
+    /// Packages of the reference not installed locally, with the reference version
+    pub missing: Vec<(String, String)>,
+}
+
+impl Drift {
+    pub fn between(local: &Lockfile, reference: &Lockfile) -> Self {
+        let local_versions: BTreeMap<String, &str> = local.versions().collect();
+        let mut drift = Drift::default();
+        for (name, reference_version) in reference.versions() {
+            let Some(local_version) = local_versions.get(&name) else {
+                drift.missing.push((name, reference_version.to_string()));
+                continue;
+            };
+            let versions = (name, local_version.to_string(), reference_version.to_string());
+            match compare_versions(local_version, reference_version) {
+                Ordering::Greater => drift.ahead.push(versions),
+                Ordering::Less => drift.behind.push(versions),
+                Ordering::Equal => {}
+            }
+        }
+        drift
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ahead.is_empty() && self.behind.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "\t no drift");
+        }
+        for (name, local, reference) in &self.ahead {
+            writeln!(f, "\t ahead: {} {} (reference {})", name, local, reference)?;
+        }
+        for (name, local, reference) in &self.behind {
+            writeln!(f, "\t behind: {} {} (reference {})", name, local, reference)?;
+        }
+        for (name, reference) in &self.missing {
+            writeln!(f, "\t missing: {} {}", name, reference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares versions component by component (`1.10` > `1.9`, `3.1_1` > `3.1`), numbers numerically and words lexically
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let components = |version: &str| -> Vec<String> {
+        version.split(|c: char| !c.is_ascii_alphanumeric()).filter(|part| !part.is_empty()).map(String::from).collect()
+    };
+    let (a, b) = (components(a), components(b));
+    for (x, y) in a.iter().zip(&b) {
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockfile(host: &str, formulae: &[(&str, &str)], casks: &[(&str, &str)]) -> Lockfile {
+        Lockfile {
+            generated_at: Utc::now(),
+            host: Some(host.to_string()),
+            formulae: formulae
+                .iter()
+                .map(|(name, version)| {
+                    let locked = LockedFormula { version: version.to_string(), revision: 0, tap: None, poured_from_bottle: true };
+                    (name.to_string(), locked)
+                })
+                .collect(),
+            casks: casks
+                .iter()
+                .map(|(token, version)| (token.to_string(), LockedCask { version: version.to_string(), tap: None }))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn should_compare_versions_component_by_component() {
+        assert_eq!(compare_versions("1.10.0", "1.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("3.1_1", "3.1"), Ordering::Greater);
+        assert_eq!(compare_versions("20.18.1", "20.19.5"), Ordering::Less);
+        assert_eq!(compare_versions("4.48.0,207573", "4.48.0,207573"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0rc2", "1.0rc1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn should_lock_linked_keg_with_bottle_and_tap() {
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{
+                "formulae": [{"name": "jq", "tap": "homebrew/core", "revision": 1, "linked_keg": "1.7.1_1",
+                              "installed": [{"version": "1.7.1_1", "poured_from_bottle": true}, {"version": "1.8.0"}]}],
+                "casks": [{"token": "firefox", "tap": "homebrew/cask", "installed": "144.0"}]
+            }"#,
+        )
+        .unwrap();
+        let lockfile = Lockfile::new(&info, None, Utc::now());
+        assert_eq!(
+            lockfile.formulae["jq"],
+            LockedFormula {
+                version: "1.7.1_1".to_string(),
+                revision: 1,
+                tap: Some("homebrew/core".to_string()),
+                poured_from_bottle: true
+            }
+        );
+        assert_eq!(lockfile.casks["firefox"].version, "144.0");
+    }
+
+    #[test]
+    fn should_report_packages_ahead_behind_and_missing() {
+        let local = lockfile("alice-mbp", &[("jq", "1.8.0"), ("node", "22.1.0"), ("wget", "1.24.5")], &[]);
+        let reference = lockfile(
+            "fleet",
+            &[("jq", "1.7.1"), ("node", "22.11.0"), ("wget", "1.24.5"), ("go", "1.25.3")],
+            &[("firefox", "144.0")],
+        );
+        let drift = Drift::between(&local, &reference);
+        assert_eq!(drift.ahead, vec![("jq".to_string(), "1.8.0".to_string(), "1.7.1".to_string())]);
+        assert_eq!(drift.behind, vec![("node".to_string(), "22.1.0".to_string(), "22.11.0".to_string())]);
+        assert_eq!(
+            drift.missing,
+            vec![("go".to_string(), "1.25.3".to_string()), ("firefox (cask)".to_string(), "144.0".to_string())]
+        );
+    }
+
+    #[test]
+    fn should_read_fleet_directory_without_own_lockfile() {
+        let dir = std::env::temp_dir().join(format!("brew-maintainer-fleet-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        lockfile("alice-mbp", &[], &[]).save(&dir.join("alice-mbp.json")).unwrap();
+        lockfile("bob-mbp", &[], &[]).save(&dir.join("bob-mbp.json")).unwrap();
+        let references = references(&dir, Some("alice-mbp")).unwrap();
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].1.host.as_deref(), Some("bob-mbp"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,12 +5,14 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, fmt};
 
+use crate::paths;
+
 pub fn init_logging() {
     // Choose log directory (works for both architectures)
-    let log_dir = if fs::metadata("/opt/homebrew/var/log").is_ok() { "/opt/homebrew/var/log" } else { "/usr/local/var/log" };
+    let log_dir = paths::log_dir();
 
     // Make sure directory exists
-    let _ = fs::create_dir_all(log_dir);
+    let _ = fs::create_dir_all(&log_dir);
 
     // Initialize tracing subscriber
     let file_appender = tracing_appender::rolling::daily(log_dir, "brew-maintainer.log");
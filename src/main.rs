@@ -1,31 +1,129 @@
+mod autoremove;
 mod brew_command;
+mod brewfile;
+mod caveats;
+mod cleanup;
+mod cli;
+mod config;
+mod dependencies;
+mod deprecations;
+mod disk_space;
 mod formulae;
+mod health;
+mod history;
+mod info;
+mod kegs;
+mod linkage;
+mod lockfile;
 mod logging;
 mod maintenance_command;
+mod parser;
+mod paths;
+mod plan;
+mod platform;
+mod processes;
+mod report;
 mod service;
+mod services;
+mod smoke_tests;
+mod snapshot;
+mod source_builds;
+mod taps;
+mod update;
 
 use crate::{
+    cli::Command,
+    config::Config,
+    history::History,
+    lockfile::{Drift, Lockfile},
     logging::init_logging,
     maintenance_command::RealBrewCommand,
     service::{BrewMaintainer, run_maintenance},
+    snapshot::SnapshotDiff,
 };
-use anyhow::Result;
-use chrono::Local;
-use tracing::info;
+use anyhow::{Context, Result};
+use chrono::{Duration, Local, Utc};
+use std::{fmt::Write, path::PathBuf};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli_command = cli::parse(std::env::args().skip(1))?;
     init_logging();
     let start_time = Local::now();
     info!("=== Brew Maintenance Started at {} ===>|", start_time);
-    let command = BrewMaintainer::new(&RealBrewCommand);
+    let config = Config::load(&paths::config_file())?;
+    let mut history = History::load(&paths::history_file()).unwrap_or_else(|e| {
+        warn!("starting with an empty history: {:#}", e);
+        History::default()
+    });
+    let drift_reference = config.drift.reference.clone();
+    let command = BrewMaintainer::new(&RealBrewCommand).with_config(config);
 
-    match run_maintenance(&command).await {
-        Ok(_) => info!("|<============= Run complete."),
-        Err(e) => info!("|<============= Run failed: {}", e),
+    let outcome = match cli_command {
+        Command::Run => run_maintenance(&command, &mut history)
+            .await
+            .map(|report| info!("|<============= Run complete.\n{}", report))
+            .context("Run failed"),
+        Command::Rollback { package_name } => command
+            .rollback(&package_name, &mut history)
+            .map(|record| {
+                info!("|<============= Rolled back {} from {} to {}", record.name, record.from_version, record.to_version)
+            })
+            .with_context(|| format!("Rollback of {} failed", package_name)),
+        Command::Pin { package_name, days } => {
+            let expires_at = days.map(|days| Utc::now() + Duration::days(days.into()));
+            command
+                .pin(&package_name, expires_at, &mut history)
+                .map(|()| match expires_at {
+                    Some(expires_at) => info!("|<============= Pinned {} until {}", package_name, expires_at),
+                    None => info!("|<============= Pinned {}", package_name),
+                })
+                .with_context(|| format!("Pin of {} failed", package_name))
+        }
+        Command::Unpin { package_name } => command
+            .unpin(&package_name, &mut history)
+            .map(|()| info!("|<============= Unpinned {}", package_name))
+            .with_context(|| format!("Unpin of {} failed", package_name)),
+        Command::Diff { snapshots } => {
+            let names = snapshots.as_ref().map(|(before, after)| (before.as_str(), after.as_str()));
+            snapshot::select(&paths::snapshot_dir(), names)
+                .map(|(before, after)| {
+                    info!(
+                        "|<============= Changes between {} ({}) and {} ({}):\n{}",
+                        before.label,
+                        before.taken_at,
+                        after.label,
+                        after.taken_at,
+                        SnapshotDiff::between(&before, &after)
+                    )
+                })
+                .context("Diff failed")
+        }
+        Command::Drift { reference } => drift(reference.map(PathBuf::from).or(drift_reference))
+            .map(|drifts| info!("|<============= Drift from the reference lockfiles:\n{}", drifts))
+            .context("Drift failed"),
+    };
+    if let Err(e) = &outcome {
+        error!("|<============= {:#}", e);
+    }
+    if let Err(e) = history.save(&paths::history_file()) {
+        warn!("failed to save history: {:#}", e);
     }
     let end_time = Local::now();
     let duration = end_time - start_time;
     info!("=== Brew Maintenance Finished at {} taking {} ===>|", end_time, duration);
-    Ok(())
+    // a failed command exits with an error status
+    outcome
+}
+
+/// Drift of the local lockfile from each reference lockfile
+fn drift(reference: Option<PathBuf>) -> Result<String> {
+    let reference = reference.context("no reference lockfile given nor configured in drift.reference")?;
+    let local = Lockfile::load(&paths::lockfile())?;
+    let mut drifts = String::new();
+    for (path, lockfile) in lockfile::references(&reference, local.host.as_deref())? {
+        writeln!(drifts, "{}:\n{}", path.display(), Drift::between(&local, &lockfile))?;
+    }
+    Ok(drifts)
 }
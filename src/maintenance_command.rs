@@ -1,10 +1,10 @@
 use std::process::Command as StdCommand;
-use std::time::Duration as StdDuration;
+use std::time::{Duration as StdDuration, Instant};
 use std::{
     collections::HashMap,
     env,
     process::Stdio,
-    sync::mpsc::{Sender, channel},
+    sync::mpsc::{Sender, TryRecvError, channel},
     thread,
 };
 use tokio::process::Child as TokioChild;
@@ -13,6 +13,10 @@ use tracing::info;
 
 use crate::brew_command::{BrewCommand, BrewError, CommandExecutor};
 
+const EVENT_POLL_INTERVAL: StdDuration = StdDuration::from_millis(100);
+/// How long to wait for the rest of the output once brew exited (processes it spawned may keep stdout open)
+const OUTPUT_DRAIN_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+
 pub struct RealBrewCommand;
 
 impl CommandExecutor for RealBrewCommand {
@@ -27,8 +31,10 @@ impl CommandExecutor for RealBrewCommand {
         if output.status.success() {
             String::from_utf8(output.stdout).map_err(|e| BrewError::ExecutionFailed(e.to_string()))
         } else {
+            // some commands (e.g. `brew linkage --test`) report their failure on stdout
             let stderr = String::from_utf8_lossy(&output.stderr);
-            Err(BrewError::ExecutionFailed(stderr.to_string()))
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            Err(BrewError::ExecutionFailed(format!("{}{}", stderr, stdout).trim().to_string()))
         }
     }
     fn envs(&self) -> HashMap<&'static str, String> {
@@ -42,7 +48,7 @@ impl CommandExecutor for RealBrewCommand {
         envs
     }
 
-    async fn execute_with_timeout<'a>(&self, cmd: &BrewCommand<'a>, timeout: chrono::Duration) -> Result<(), BrewError> {
+    async fn execute_with_timeout<'a>(&self, cmd: &BrewCommand<'a>, timeout: chrono::Duration) -> Result<String, BrewError> {
         let std_timeout = StdDuration::from_millis(timeout.num_milliseconds().max(0) as u64);
         let args = cmd.to_args();
         let env_map = cmd.to_env();
@@ -56,9 +62,7 @@ impl CommandExecutor for RealBrewCommand {
         // Spawn monitoring threads for stdout/stderr
         let stdout = child.stdout.take().unwrap();
         let error_tx_stdout = error_tx.clone();
-        tokio::spawn(async move {
-            monitor_async_output(stdout, error_tx_stdout).await;
-        });
+        let stdout_monitor = tokio::spawn(async move { monitor_async_output(stdout, error_tx_stdout).await });
 
         let stderr = child.stderr.take().unwrap();
         let error_tx_stderr = error_tx.clone();
@@ -67,12 +71,10 @@ impl CommandExecutor for RealBrewCommand {
         });
 
         // Spawn completion monitor thread
-        let completion_thread = spawn_completion_monitor(child, event_tx.clone());
-
-        // Spawn timeout thread
-        let timeout_thread = spawn_timeout_monitor(std_timeout, event_tx);
+        let completion_thread = spawn_completion_monitor(child, event_tx);
+        let deadline = Instant::now() + std_timeout;
 
-        // Main thread blocks waiting for first event
+        // Main thread waits for the first event
         let result = loop {
             // Check error channel (input detection) - this has priority
             if let Ok(error) = error_rx.try_recv() {
@@ -80,26 +82,28 @@ impl CommandExecutor for RealBrewCommand {
                 break Err(error);
             }
 
-            // Block on event channel (completion or timeout)
-            match event_rx.recv() {
-                Ok(ProcessEvent::Error(error)) => {
-                    // Timeout occurred
-                    kill_process_by_pid(child_id);
-                    break Err(error);
-                }
+            if Instant::now() >= deadline {
+                kill_process_by_pid(child_id);
+                break Err(BrewError::Timeout);
+            }
+
+            // Poll event channel (completion), yielding so other brew processes can be awaited concurrently
+            match event_rx.try_recv() {
                 Ok(ProcessEvent::Completed(Ok(status))) if status.success() => {
                     // Process completed successfully
                     break Ok(());
                 }
                 Ok(ProcessEvent::Completed(Ok(status))) => {
-                    // Process completed with error
-                    break Err(BrewError::ExecutionFailed(format!("Process exited with code: {:?}", status.code())));
+                    // Process completed with error, its output is read below
+                    let reason = format!("Process exited with code: {:?}", status.code());
+                    break Err(BrewError::FailedWithOutput { reason, output: String::new() });
                 }
                 Ok(ProcessEvent::Completed(Err(e))) => {
                     // Error waiting for process
                     break Err(BrewError::ExecutionFailed(e.to_string()));
                 }
-                Err(_) => {
+                Err(TryRecvError::Empty) => tokio::time::sleep(EVENT_POLL_INTERVAL).await,
+                Err(TryRecvError::Disconnected) => {
                     // Channel closed unexpectedly
                     break Err(BrewError::ExecutionFailed("Event channel closed".to_string()));
                 }
@@ -108,9 +112,19 @@ impl CommandExecutor for RealBrewCommand {
 
         // Cleanup: ensure process is killed if still running
         kill_process_by_pid(child_id);
-        cleanup_threads(vec![completion_thread, timeout_thread]);
+        cleanup_threads(vec![completion_thread]);
 
-        result
+        let drain = async {
+            match tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, stdout_monitor).await {
+                Ok(Ok(output)) => output,
+                _ => String::new(),
+            }
+        };
+        match result {
+            Ok(()) => Ok(drain.await),
+            Err(BrewError::FailedWithOutput { reason, .. }) => Err(BrewError::FailedWithOutput { reason, output: drain.await }),
+            Err(e) => Err(e),
+        }
     }
 }
 
@@ -147,19 +161,23 @@ fn spawn_brew_process(args: Vec<&str>, envs: HashMap<&str, String>) -> Result<To
         .map_err(|e| BrewError::ExecutionFailed(e.to_string()))
 }
 
-/// Spawns a tokio task that monitors an async stream for input requests
-async fn monitor_async_output<R: tokio::io::AsyncRead + Unpin>(stream: R, tx: Sender<BrewError>) {
+/// Monitors an async stream for input requests, returning the lines read
+async fn monitor_async_output<R: tokio::io::AsyncRead + Unpin>(stream: R, tx: Sender<BrewError>) -> String {
     use tokio::io::AsyncBufReadExt;
 
     let reader = tokio::io::BufReader::new(stream);
     let mut lines = reader.lines();
+    let mut output = String::new();
 
     while let Ok(Some(line)) = lines.next_line().await {
         if is_waiting_for_input(&line) {
             let _ = tx.send(BrewError::InputRequested);
             break;
         }
+        output.push_str(&line);
+        output.push('\n');
     }
+    output
 }
 
 fn spawn_completion_monitor(child: tokio::process::Child, tx: Sender<ProcessEvent>) -> thread::JoinHandle<()> {
@@ -176,15 +194,7 @@ fn spawn_completion_monitor(child: tokio::process::Child, tx: Sender<ProcessEven
     })
 }
 
-fn spawn_timeout_monitor(timeout: StdDuration, tx: Sender<ProcessEvent>) -> thread::JoinHandle<()> {
-    thread::spawn(move || {
-        thread::sleep(timeout);
-        let _ = tx.send(ProcessEvent::Error(BrewError::Timeout));
-    })
-}
-
 enum ProcessEvent {
-    Error(BrewError),
     Completed(Result<std::process::ExitStatus, std::io::Error>),
 }
 
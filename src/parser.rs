@@ -0,0 +1,194 @@
+use serde::de::DeserializeOwned;
+
+use crate::brew_command::BrewError;
+
+/// Maximum length of the output excerpt carried by parse errors
+const SNIPPET_LENGTH: usize = 200;
+
+/// Parses the JSON document printed by brew, ignoring anything printed around it (warnings, hints, ...).
+/// Only the first top-level document is considered: when it does not match `T` the error is reported rather than
+/// falling back to a nested object that happens to match
+pub fn parse_json<T: DeserializeOwned>(output: &str) -> Result<T, BrewError> {
+    let document = json_documents(output)
+        .next()
+        .ok_or_else(|| BrewError::ParseFailed { reason: "no JSON document found".to_string(), snippet: snippet(output) })?;
+    serde_json::from_str(document).map_err(|e| BrewError::ParseFailed { reason: e.to_string(), snippet: snippet(document) })
+}
+
+/// Balanced `{...}` or `[...]` blocks of the output that are valid JSON and start at the beginning of a line, as brew
+/// prints them; indented blocks are nested in a pretty-printed document
+fn json_documents(output: &str) -> impl Iterator<Item = &str> {
+    let mut line_start = true;
+    output
+        .char_indices()
+        .filter(move |(_, c)| {
+            let starts_document = line_start && (*c == '{' || *c == '[');
+            line_start = *c == '\n';
+            starts_document
+        })
+        .filter_map(|(start, _)| balanced_end(&output[start..]).map(|end| &output[start..start + end]))
+        .filter(|candidate| serde_json::from_str::<serde_json::Value>(candidate).is_ok())
+}
+
+/// Length of the block opened by the first character of `text`, if it is closed
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn snippet(text: &str) -> String {
+    text.trim().chars().take(SNIPPET_LENGTH).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formulae::{Cask, Formula, OutdatedPackages};
+
+    const OUTDATED_V2: &str = include_str!("../testdata/brew_outdated_v2.json");
+
+    #[test]
+    fn should_parse_json_preceded_and_followed_by_brew_messages() {
+        let output = format!(
+            "Warning: Treating {{foo}} as a formula.\n==> Auto-updating Homebrew...\n{}\nHint: run `brew cleanup`\n",
+            OUTDATED_V2
+        );
+        let outdated: OutdatedPackages = parse_json(&output).unwrap();
+        assert_eq!(outdated.formulae.len(), 3);
+        assert_eq!(outdated.casks.len(), 2);
+    }
+
+    #[test]
+    fn should_report_offending_snippet_when_no_json_is_present() {
+        let error = parse_json::<OutdatedPackages>("Error: Permission denied @ dir_s_mkdir").unwrap_err();
+        assert!(matches!(error, BrewError::ParseFailed { ref snippet, .. } if snippet == "Error: Permission denied @ dir_s_mkdir"));
+    }
+
+    #[test]
+    fn should_report_schema_mismatch_as_parse_error() {
+        let error = parse_json::<OutdatedPackages>(r#"{"formulae": [{"name": 42}]}"#).unwrap_err();
+        assert!(matches!(error, BrewError::ParseFailed { ref snippet, .. } if snippet.starts_with(r#"{"formulae""#)));
+    }
+
+    #[test]
+    fn should_not_fall_back_to_nested_object_when_pretty_printed_document_mismatches() {
+        let output = "==> Auto-updating Homebrew...\n{\n  \"formulae\": {\n    \"jq\": 1\n  },\n  \"casks\": [\n    {}\n  ]\n}\n";
+        let error = parse_json::<OutdatedPackages>(output).unwrap_err();
+        assert!(matches!(error, BrewError::ParseFailed { ref snippet, .. } if snippet.starts_with("{\n  \"formulae\"")));
+    }
+
+    /// Deterministic xorshift generator, so that failures are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn text(&mut self, alphabet: &[char], max_len: usize) -> String {
+            let len = self.below(max_len + 1);
+            (0..len).map(|_| alphabet[self.below(alphabet.len())]).collect()
+        }
+    }
+
+    const NOISE: &[char] = &['a', 'Z', ' ', ':', '=', '>', '{', '}', '[', ']', '"', '\\', '\n', 'é', '🍺', ',', '0'];
+    const NAME: &[char] = &['a', 'b', 'q', '@', '-', '.', '"', '\\', '{', ']', '3', 'ü'];
+
+    fn random_outdated(rng: &mut Rng) -> OutdatedPackages {
+        let formulae = (0..rng.below(4))
+            .map(|_| Formula {
+                name: rng.text(NAME, 12),
+                installed_versions: (0..rng.below(3)).map(|_| rng.text(NAME, 6)).collect(),
+                current_version: rng.text(NAME, 6),
+                pinned: rng.below(2) == 0,
+                pinned_version: if rng.below(2) == 0 { Some(rng.text(NAME, 6)) } else { None },
+            })
+            .collect();
+        let casks = (0..rng.below(4))
+            .map(|_| Cask {
+                name: rng.text(NAME, 12),
+                installed_versions: (0..rng.below(3)).map(|_| rng.text(NAME, 6)).collect(),
+                current_version: rng.text(NAME, 6),
+            })
+            .collect();
+        OutdatedPackages { formulae, casks }
+    }
+
+    /// Noise lines brew could print around the document: never a complete JSON value on their own
+    fn random_noise_line(rng: &mut Rng) -> String {
+        let line = rng.text(NOISE, 40).replace('\n', " ");
+        if json_documents(&line).next().is_some() { String::from("Warning: noise") } else { line }
+    }
+
+    #[test]
+    fn property_any_document_survives_surrounding_noise() {
+        let mut rng = Rng(0x5eed_cafe_f00d_beef);
+        for _ in 0..500 {
+            let expected = random_outdated(&mut rng);
+            let json = if rng.below(2) == 0 {
+                serde_json::to_string(&expected).unwrap()
+            } else {
+                serde_json::to_string_pretty(&expected).unwrap()
+            };
+            let prefix: Vec<String> = (0..rng.below(4)).map(|_| random_noise_line(&mut rng)).collect();
+            let suffix: Vec<String> = (0..rng.below(4)).map(|_| random_noise_line(&mut rng)).collect();
+            let output = format!("{}\n{}\n{}", prefix.join("\n"), json, suffix.join("\n"));
+
+            let parsed: OutdatedPackages = parse_json(&output).unwrap_or_else(|e| panic!("{} for output {:?}", e, output));
+            assert_eq!(parsed.formulae, expected.formulae, "output: {:?}", output);
+            assert_eq!(parsed.casks, expected.casks, "output: {:?}", output);
+        }
+    }
+
+    #[test]
+    fn fuzz_arbitrary_output_never_panics() {
+        let mut rng = Rng(0x0dd_ba11);
+        for _ in 0..2000 {
+            let output = rng.text(NOISE, 120);
+            let _ = parse_json::<OutdatedPackages>(&output);
+            let _ = parse_json::<serde_json::Value>(&output);
+        }
+    }
+
+    #[test]
+    fn fuzz_truncated_documents_are_errors_not_panics() {
+        let mut rng = Rng(0xfeed);
+        for _ in 0..300 {
+            let json = serde_json::to_string(&random_outdated(&mut rng)).unwrap();
+            let cut = json.char_indices().map(|(i, _)| i).nth(rng.below(json.chars().count())).unwrap_or(0);
+            let result = parse_json::<OutdatedPackages>(&json[..cut]);
+            assert!(matches!(result, Err(BrewError::ParseFailed { .. })), "truncated {:?}", &json[..cut]);
+        }
+    }
+}
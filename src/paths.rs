@@ -0,0 +1,38 @@
+use std::{env, fs, path::PathBuf};
+
+const CONFIG_ENV: &str = "BREW_MAINTAINER_CONFIG";
+
+// Homebrew lives under /opt/homebrew on Apple Silicon and /usr/local on Intel
+pub fn homebrew_prefix() -> PathBuf {
+    if fs::metadata("/opt/homebrew").is_ok() { PathBuf::from("/opt/homebrew") } else { PathBuf::from("/usr/local") }
+}
+
+pub fn log_dir() -> PathBuf {
+    homebrew_prefix().join("var/log")
+}
+
+pub fn config_file() -> PathBuf {
+    env::var(CONFIG_ENV).map(PathBuf::from).unwrap_or_else(|_| homebrew_prefix().join("etc/brew-maintainer.json"))
+}
+
+pub fn state_dir() -> PathBuf {
+    homebrew_prefix().join("var/brew-maintainer")
+}
+
+pub fn history_file() -> PathBuf {
+    state_dir().join("history.json")
+}
+
+/// Shared Brewfile merged with the overlay of this machine, as given to `brew bundle`
+pub fn desired_brewfile() -> PathBuf {
+    state_dir().join("Brewfile")
+}
+
+/// Exact versions installed after the last run
+pub fn lockfile() -> PathBuf {
+    state_dir().join("packages.lock.json")
+}
+
+pub fn snapshot_dir() -> PathBuf {
+    state_dir().join("snapshots")
+}
@@ -0,0 +1,61 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Duration;
+
+use crate::{brew_command::PackageKind, config::TimeoutConfig, formulae::Package, history::History};
+
+/// Packages to upgrade, in upgrade order, with the timeouts decided for this run
+#[derive(Debug, Clone, Default)]
+pub struct UpgradePlan<'a> {
+    pub packages: Vec<Package<'a>>,
+    /// Timeouts replacing the computed ones for this run only
+    pub timeouts: HashMap<(PackageKind, String), Duration>,
+    /// Application bundles of the planned casks
+    pub cask_apps: HashMap<String, Vec<String>>,
+    /// Formulae whose brew service will be restarted after the upgrade
+    pub restarted_services: HashSet<String>,
+    /// Formulae without a bottle are not upgraded, whatever the source build policy
+    pub source_builds_disabled: bool,
+    /// Planned formulae each planned formula depends on, unknown without the installed packages info
+    pub dependencies: Option<HashMap<&'a str, HashSet<&'a str>>>,
+}
+
+impl<'a> UpgradePlan<'a> {
+    pub fn new(packages: Vec<Package<'a>>) -> Self {
+        Self {
+            packages,
+            timeouts: HashMap::new(),
+            cask_apps: HashMap::new(),
+            restarted_services: HashSet::new(),
+            source_builds_disabled: false,
+            dependencies: None,
+        }
+    }
+
+    /// Whether upgrading `upgraded` may also upgrade `other`: brew upgrades the outdated dependencies and dependents of
+    /// what it upgrades. Always true when the dependencies are unknown
+    pub fn may_upgrade_along(&self, upgraded: &Package, other: &Package) -> bool {
+        let Some(dependencies) = &self.dependencies else {
+            return true;
+        };
+        let depends_on = |package: &Package, dependency: &Package| {
+            dependencies.get(package.name()).is_some_and(|deps| deps.contains(dependency.name()))
+        };
+        upgraded.kind() == PackageKind::Formula
+            && other.kind() == PackageKind::Formula
+            && (depends_on(upgraded, other) || depends_on(other, upgraded))
+    }
+
+    /// Timeout of the package for this run, configured overrides win over the timeouts decided for the run
+    pub fn timeout_for(&self, package: &Package, config: &TimeoutConfig, history: &History) -> Duration {
+        let key = (package.kind(), package.name().to_string());
+        match self.timeouts.get(&key) {
+            Some(timeout) if !config.overrides.contains_key(package.name()) => *timeout,
+            _ => config.timeout_for(package.name(), history),
+        }
+    }
+
+    pub fn batch_timeout_for(&self, packages: &[Package], config: &TimeoutConfig, history: &History) -> Duration {
+        packages.iter().map(|package| self.timeout_for(package, config, history)).fold(Duration::zero(), |total, t| total + t)
+    }
+}
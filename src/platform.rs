@@ -0,0 +1,63 @@
+use std::{env::consts, process::Command};
+
+/// Bottle tag brew uses for this machine (e.g. `arm64_sequoia`, `sonoma`, `x86_64_linux`), if it can be determined
+pub fn bottle_tag() -> Option<String> {
+    match consts::OS {
+        "macos" => {
+            let output = Command::new("sw_vers").arg("-productVersion").output().ok()?;
+            let version = String::from_utf8(output.stdout).ok()?;
+            macos_bottle_tag(version.trim(), consts::ARCH)
+        }
+        "linux" => Some(format!("{}_linux", if consts::ARCH == "aarch64" { "arm64" } else { consts::ARCH })),
+        _ => None,
+    }
+}
+
+/// Host name without its domain (e.g. `alice-mbp` for `alice-mbp.local`)
+pub fn short_host_name() -> Option<String> {
+    let host_name = nix::unistd::gethostname().ok()?.into_string().ok()?;
+    host_name.split('.').next().map(String::from)
+}
+
+/// macOS releases brew builds bottles for, newest first, with their major version
+const MACOS_RELEASES: [(u32, &str); 6] =
+    [(26, "tahoe"), (15, "sequoia"), (14, "sonoma"), (13, "ventura"), (12, "monterey"), (11, "big_sur")];
+
+fn macos_bottle_tag(product_version: &str, arch: &str) -> Option<String> {
+    let major: u32 = product_version.split('.').next()?.parse().ok()?;
+    let (_, name) = MACOS_RELEASES.iter().find(|(version, _)| *version == major)?;
+    Some(if arch == "aarch64" { format!("arm64_{}", name) } else { name.to_string() })
+}
+
+/// Bottle tags brew pours on a machine with the given tag, in order of preference: the tag itself, `all`, then the
+/// bottles built for older macOS releases on the same architecture
+pub fn compatible_bottle_tags(tag: &str) -> Vec<String> {
+    let mut tags = vec![tag.to_string(), "all".to_string()];
+    let (arch, name) = match tag.strip_prefix("arm64_") {
+        Some(name) => ("arm64_", name),
+        None => ("", tag),
+    };
+    if let Some(position) = MACOS_RELEASES.iter().position(|(_, release)| *release == name) {
+        tags.extend(MACOS_RELEASES[position + 1..].iter().map(|(_, older)| format!("{}{}", arch, older)));
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_map_macos_version_and_arch_to_bottle_tag() {
+        assert_eq!(macos_bottle_tag("15.6.1", "aarch64"), Some("arm64_sequoia".to_string()));
+        assert_eq!(macos_bottle_tag("14.0", "x86_64"), Some("sonoma".to_string()));
+        assert_eq!(macos_bottle_tag("10.15.7", "x86_64"), None);
+    }
+
+    #[test]
+    fn should_accept_bottles_of_older_macos_on_the_same_architecture() {
+        assert_eq!(compatible_bottle_tags("arm64_ventura"), vec!["arm64_ventura", "all", "arm64_monterey", "arm64_big_sur"]);
+        assert_eq!(compatible_bottle_tags("monterey"), vec!["monterey", "all", "big_sur"]);
+        assert_eq!(compatible_bottle_tags("x86_64_linux"), vec!["x86_64_linux", "all"]);
+    }
+}
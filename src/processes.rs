@@ -0,0 +1,124 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunningProcess {
+    pub pid: u32,
+    pub executable: PathBuf,
+}
+
+/// Processes of the machine with their executable path: from `/proc` on Linux, from `ps` elsewhere
+pub fn running_processes() -> Vec<RunningProcess> {
+    if cfg!(target_os = "linux") { proc_processes() } else { ps_processes() }
+}
+
+fn proc_processes() -> Vec<RunningProcess> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let executable = fs::read_link(entry.path().join("exe")).ok()?;
+            // the keg of a running binary may already have been replaced
+            let executable = executable.to_string_lossy().trim_end_matches(" (deleted)").into();
+            Some(RunningProcess { pid, executable })
+        })
+        .collect()
+}
+
+fn ps_processes() -> Vec<RunningProcess> {
+    Command::new("ps")
+        .args(["-axww", "-o", "pid=,comm="])
+        .output()
+        .map(|output| parse_ps_output(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+        .into_iter()
+        .map(resolve_executable)
+        .collect()
+}
+
+/// `ps` reports the path a program was started from, e.g. the `$(brew --prefix)/bin` symlink of a formula: the keg
+/// it runs from is found by resolving it, like `/proc/<pid>/exe` on Linux
+fn resolve_executable(process: RunningProcess) -> RunningProcess {
+    match fs::canonicalize(&process.executable) {
+        Ok(executable) => RunningProcess { executable, ..process },
+        Err(_) => process,
+    }
+}
+
+fn parse_ps_output(output: &str) -> Vec<RunningProcess> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (pid, executable) = line.trim_start().split_once(char::is_whitespace)?;
+            Some(RunningProcess { pid: pid.parse().ok()?, executable: PathBuf::from(executable.trim()) })
+        })
+        .collect()
+}
+
+/// Directories holding the files of a package: its keg for formulae, its Caskroom entry and apps for casks
+pub fn package_roots(prefix: &Path, name: &str, is_cask: bool, apps: &[String]) -> Vec<PathBuf> {
+    if is_cask {
+        let mut roots = vec![prefix.join("Caskroom").join(name)];
+        roots.extend(apps.iter().map(|app| Path::new("/Applications").join(app)));
+        roots
+    } else {
+        vec![prefix.join("Cellar").join(name), prefix.join("opt").join(name)]
+    }
+}
+
+/// First process running an executable located under one of the roots
+pub fn find_running_under<'a>(processes: &'a [RunningProcess], roots: &[PathBuf]) -> Option<&'a RunningProcess> {
+    processes.iter().find(|process| roots.iter().any(|root| process.executable.starts_with(root)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_ps_output_with_spaces_in_paths() {
+        let output = "    1 /sbin/launchd\n  812 /Applications/Visual Studio Code.app/Contents/MacOS/Electron\n";
+        let processes = parse_ps_output(output);
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[1].pid, 812);
+        assert_eq!(processes[1].executable, PathBuf::from("/Applications/Visual Studio Code.app/Contents/MacOS/Electron"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_resolve_executables_started_through_the_prefix_bin_symlinks() {
+        let prefix = std::env::temp_dir().join(format!("brew-maintainer-processes-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&prefix);
+        fs::create_dir_all(prefix.join("Cellar/llvm/21.1.0/bin")).unwrap();
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(prefix.join("Cellar/llvm/21.1.0/bin/clangd"), "").unwrap();
+        std::os::unix::fs::symlink("../Cellar/llvm/21.1.0/bin/clangd", prefix.join("bin/clangd")).unwrap();
+        let process = resolve_executable(RunningProcess { pid: 42, executable: prefix.join("bin/clangd") });
+        let roots = package_roots(&fs::canonicalize(&prefix).unwrap(), "llvm", false, &[]);
+        assert_eq!(find_running_under(std::slice::from_ref(&process), &roots).map(|p| p.pid), Some(42));
+        let missing = RunningProcess { pid: 43, executable: PathBuf::from("/nonexistent/clangd") };
+        assert_eq!(resolve_executable(missing.clone()), missing);
+        fs::remove_dir_all(&prefix).unwrap();
+    }
+
+    #[test]
+    fn should_find_processes_running_from_keg_or_app() {
+        let prefix = Path::new("/opt/homebrew");
+        let processes = vec![
+            RunningProcess { pid: 10, executable: PathBuf::from("/opt/homebrew/Cellar/postgresql@16/16.10/bin/postgres") },
+            RunningProcess { pid: 11, executable: PathBuf::from("/Applications/Firefox.app/Contents/MacOS/firefox") },
+        ];
+        let postgres = package_roots(prefix, "postgresql@16", false, &[]);
+        assert_eq!(find_running_under(&processes, &postgres).map(|p| p.pid), Some(10));
+        let postgres_17 = package_roots(prefix, "postgresql@17", false, &[]);
+        assert_eq!(find_running_under(&processes, &postgres_17), None);
+        let firefox = package_roots(prefix, "firefox", true, &["Firefox.app".to_string()]);
+        assert_eq!(find_running_under(&processes, &firefox).map(|p| p.pid), Some(11));
+    }
+}
@@ -0,0 +1,451 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    brew_command::PackageKind, brewfile::BrewfileDiff, cleanup, deprecations::Deprecation, health::DoctorWarning,
+    update::UpdateSummary,
+};
+
+/// Outcome of a single package during the upgrade phase
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpgradeStatus {
+    Upgraded,
+    /// Upgraded but a smoke test failed, with the failure
+    Unhealthy(String),
+    /// Not attempted on purpose, with the reason
+    Skipped(String),
+    /// Download failed before upgrading, the upgrade was not attempted
+    FetchFailed(String),
+    /// Not attempted because the estimated space it needs is not available, with the figures
+    InsufficientDiskSpace(String),
+    /// Already upgraded by brew while upgrading another package
+    UpgradedAsDependency,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageReport {
+    pub name: String,
+    /// Tells apart a formula and a cask sharing the name
+    pub kind: PackageKind,
+    pub status: UpgradeStatus,
+    /// `==> Caveats` printed by brew while upgrading the package
+    pub caveats: Option<String>,
+}
+
+impl PackageReport {
+    /// Report of a formula, `with_kind` tells a cask
+    pub fn new(name: &str, status: UpgradeStatus) -> Self {
+        Self { name: name.to_string(), kind: PackageKind::Formula, status, caveats: None }
+    }
+
+    pub fn with_kind(mut self, kind: PackageKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_caveats(mut self, caveats: Option<String>) -> Self {
+        self.caveats = caveats;
+        self
+    }
+
+    pub fn is_upgraded(&self) -> bool {
+        matches!(self.status, UpgradeStatus::Upgraded | UpgradeStatus::UpgradedAsDependency | UpgradeStatus::Unhealthy(_))
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.status, UpgradeStatus::Failed(_) | UpgradeStatus::FetchFailed(_))
+    }
+}
+
+impl Display for PackageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.status {
+            UpgradeStatus::Upgraded => writeln!(f, "\t - {} => upgraded", self.name),
+            UpgradeStatus::UpgradedAsDependency => {
+                writeln!(f, "\t - {} => upgraded as a side effect of another upgrade", self.name)
+            }
+            UpgradeStatus::Unhealthy(reason) => writeln!(f, "\t - {} => upgraded but unhealthy: {}", self.name, reason),
+            UpgradeStatus::Skipped(reason) => writeln!(f, "\t - {} => skipped: {}", self.name, reason),
+            UpgradeStatus::FetchFailed(reason) => writeln!(f, "\t - {} => fetch failed: {}", self.name, reason),
+            UpgradeStatus::InsufficientDiskSpace(reason) => {
+                writeln!(f, "\t - {} => insufficient disk space: {}", self.name, reason)
+            }
+            UpgradeStatus::Failed(reason) => writeln!(f, "\t - {} => failed: {}", self.name, reason),
+        }?;
+        if let Some(caveats) = &self.caveats {
+            writeln!(f, "\t   caveats:")?;
+            for line in caveats.lines() {
+                writeln!(f, "\t     {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Restart of a brew service after its formula was upgraded
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceReport {
+    pub name: String,
+    pub restarted: bool,
+    pub detail: String,
+}
+
+impl Display for ServiceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = if self.restarted { "restarted" } else { "failed to restart" };
+        writeln!(f, "\t - {} => {}: {}", self.name, outcome, self.detail)
+    }
+}
+
+/// Installed dependent of an upgraded formula whose linkage is broken
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkageReport {
+    pub name: String,
+    /// Upgraded formulae it depends on
+    pub upgraded_dependencies: Vec<String>,
+    /// Broken or missing libraries as listed by `brew linkage`
+    pub broken: Vec<String>,
+    /// Outcome of the reinstall, when attempted
+    pub reinstall: Option<Result<(), String>>,
+}
+
+impl Display for LinkageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "\t - {} (after upgrading {}) => broken linkage", self.name, self.upgraded_dependencies.join(", "))?;
+        for library in &self.broken {
+            writeln!(f, "\t\t{}", library)?;
+        }
+        match &self.reinstall {
+            Some(Ok(())) => writeln!(f, "\t\treinstalled"),
+            Some(Err(e)) => writeln!(f, "\t\treinstall failed: {}", e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Convergence of the machine to the desired Brewfile
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconcileReport {
+    pub diff: BrewfileDiff,
+    pub report_only: bool,
+    /// Outcome of `brew bundle install`, when the Brewfile was not satisfied
+    pub install: Option<Result<(), String>>,
+    /// Unlisted leaves uninstalled, with the outcome
+    pub uninstalled: Vec<(String, Result<(), String>)>,
+}
+
+impl Display for ReconcileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.report_only {
+            writeln!(f, "\t(report only)")?;
+        }
+        if self.diff.is_empty() {
+            writeln!(f, "\t => in sync with the Brewfile")?;
+        }
+        write!(f, "{}", self.diff)?;
+        match &self.install {
+            Some(Ok(())) => writeln!(f, "\t => brew bundle install succeeded")?,
+            Some(Err(e)) => writeln!(f, "\t => brew bundle install failed: {}", e)?,
+            None => {}
+        }
+        for (name, outcome) in &self.uninstalled {
+            match outcome {
+                Ok(()) => writeln!(f, "\t => {} uninstalled", name)?,
+                Err(e) => writeln!(f, "\t => {} uninstall failed: {}", name, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Orphaned dependencies found by `brew autoremove --dry-run`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AutoremoveReport {
+    pub removed: Vec<String>,
+    /// Orphans kept because they are on the protect-list or a protected formula depends on them
+    pub protected: Vec<String>,
+    /// Orphans brew failed to uninstall, with the error
+    pub failed: Vec<(String, String)>,
+    /// Failure of `brew autoremove` itself
+    pub error: Option<String>,
+}
+
+impl Display for AutoremoveReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for name in &self.removed {
+            writeln!(f, "\t - {} => removed", name)?;
+        }
+        for name in &self.protected {
+            writeln!(f, "\t - {} => kept: protected", name)?;
+        }
+        for (name, e) in &self.failed {
+            writeln!(f, "\t - {} => failed: {}", name, e)?;
+        }
+        match &self.error {
+            Some(e) => writeln!(f, "\t => failed: {}", e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Pins enforced and expired by the run, and the formulae they hold back
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PinReport {
+    /// Configured pins brew did not have yet
+    pub pinned: Vec<String>,
+    /// Pins made by the maintainer that expired or are no longer configured, with the reason
+    pub unpinned: Vec<(String, String)>,
+    pub held: Vec<HeldPackage>,
+    pub errors: Vec<String>,
+}
+
+impl PinReport {
+    pub fn holds(&self, name: &str) -> bool {
+        self.held.iter().any(|held| held.name == name)
+    }
+}
+
+impl Display for PinReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for name in &self.pinned {
+            writeln!(f, "\t - {} => pinned as configured", name)?;
+        }
+        for (name, reason) in &self.unpinned {
+            writeln!(f, "\t - {} => unpinned: {}", name, reason)?;
+        }
+        for held in &self.held {
+            write!(f, "{}", held)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "\t => failed: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pinned formula, with the newer version it is kept from when outdated
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeldPackage {
+    pub name: String,
+    pub version: Option<String>,
+    pub available: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Display for HeldPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let version = self.version.as_deref().unwrap_or("unknown version");
+        match &self.available {
+            Some(available) => write!(f, "\t - {} => pinned at {}, {} available", self.name, version, available)?,
+            None => write!(f, "\t - {} => pinned at {}, up to date", self.name, version)?,
+        }
+        match self.expires_at {
+            Some(expires_at) => writeln!(f, " (until {})", expires_at.format("%Y-%m-%d %H:%M UTC")),
+            None => writeln!(f),
+        }
+    }
+}
+
+/// Installed taps worth a look: unused, failing to update or not trusted
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TapReport {
+    /// Third-party taps no installed package comes from
+    pub unused: Vec<String>,
+    /// Unused taps removed by the run
+    pub untapped: Vec<String>,
+    pub failed_updates: Vec<String>,
+    /// Third-party taps not on the trust list, with their remote
+    pub untrusted: Vec<(String, Option<String>)>,
+    pub errors: Vec<String>,
+}
+
+impl Display for TapReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for tap in &self.unused {
+            let outcome = if self.untapped.contains(tap) { "untapped" } else { "kept" };
+            writeln!(f, "\t - {} => no installed package, {}", tap, outcome)?;
+        }
+        for tap in &self.failed_updates {
+            writeln!(f, "\t - {} => failed to update", tap)?;
+        }
+        for (tap, remote) in &self.untrusted {
+            writeln!(f, "\t - {} => not trusted: {}", tap, remote.as_deref().unwrap_or("unknown remote"))?;
+        }
+        for error in &self.errors {
+            writeln!(f, "\t => failed: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CleanupReport {
+    /// Disk space `brew cleanup` reports as freed
+    pub reclaimed_bytes: Option<u64>,
+    /// Previous kegs removed beyond the versions kept, as `name version`
+    pub removed_kegs: Vec<String>,
+    /// Disk space freed by all cleanups so far
+    pub total_reclaimed_bytes: u64,
+    /// Previous kegs that could not be removed, with the error
+    pub errors: Vec<String>,
+}
+
+impl CleanupReport {
+    /// Adds a later cleanup of the same run to this one
+    pub fn merge(mut self, later: CleanupReport) -> Self {
+        self.reclaimed_bytes = match (self.reclaimed_bytes, later.reclaimed_bytes) {
+            (None, None) => None,
+            (before, after) => Some(before.unwrap_or_default() + after.unwrap_or_default()),
+        };
+        self.removed_kegs.extend(later.removed_kegs);
+        self.errors.extend(later.errors);
+        self.total_reclaimed_bytes = later.total_reclaimed_bytes;
+        self
+    }
+}
+
+impl Display for CleanupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reclaimed = self.reclaimed_bytes.map_or_else(|| "nothing".to_string(), cleanup::format_bytes);
+        writeln!(f, "\t freed {} (total {})", reclaimed, cleanup::format_bytes(self.total_reclaimed_bytes))?;
+        for keg in &self.removed_kegs {
+            writeln!(f, "\t - {} => removed", keg)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "\t => failed: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pre-flight `brew doctor` and `brew config` check
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HealthReport {
+    /// Warnings the previous run did not report
+    pub new_warnings: Vec<DoctorWarning>,
+    /// Number of warnings already reported by the previous run
+    pub known_warnings: usize,
+    /// Problems preventing any upgrade
+    pub blocking: Vec<String>,
+    /// Problems degrading the run, with how
+    pub degradations: Vec<String>,
+    pub skip_update: bool,
+    pub skip_source_builds: bool,
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for warning in &self.new_warnings {
+            write!(f, "{}", warning)?;
+        }
+        if self.known_warnings > 0 {
+            writeln!(f, "\t ({} known warnings not repeated)", self.known_warnings)?;
+        }
+        for problem in &self.blocking {
+            writeln!(f, "\t => blocking: {}", problem)?;
+        }
+        for degradation in &self.degradations {
+            writeln!(f, "\t => degraded: {}", degradation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Installed package deprecated or disabled by Homebrew
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeprecationReport {
+    pub deprecation: Deprecation,
+    /// Outcome of the migration to the replacement, when attempted
+    pub migration: Option<Result<(), String>>,
+}
+
+impl Display for DeprecationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.deprecation)?;
+        match (&self.migration, &self.deprecation.replacement) {
+            (Some(Ok(())), Some((replacement, _))) => writeln!(f, "\t\tmigrated to {}", replacement),
+            (Some(Err(e)), _) => writeln!(f, "\t\tmigration failed: {}", e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Summary of a maintenance run
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    pub health: Option<HealthReport>,
+    pub update: Option<UpdateSummary>,
+    pub packages: Vec<PackageReport>,
+    pub pins: Option<PinReport>,
+    pub services: Vec<ServiceReport>,
+    pub linkage: Vec<LinkageReport>,
+    pub reconcile: Option<ReconcileReport>,
+    pub deprecations: Vec<DeprecationReport>,
+    pub autoremove: Option<AutoremoveReport>,
+    pub cleanup: Option<CleanupReport>,
+    pub taps: Option<TapReport>,
+}
+
+impl RunReport {
+    pub fn failed(&self) -> impl Iterator<Item = &PackageReport> {
+        self.packages.iter().filter(|p| p.is_failed())
+    }
+}
+
+impl Display for RunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(health) = self.health.as_ref().filter(|health| **health != HealthReport::default()) {
+            writeln!(f, "health:")?;
+            write!(f, "{}", health)?;
+        }
+        if let Some(update) = &self.update {
+            writeln!(f, "update:")?;
+            write!(f, "{}", update)?;
+        }
+        writeln!(f, "packages:")?;
+        for package in &self.packages {
+            write!(f, "{}", package)?;
+        }
+        if let Some(pins) = self.pins.as_ref().filter(|pins| **pins != PinReport::default()) {
+            writeln!(f, "pins:")?;
+            write!(f, "{}", pins)?;
+        }
+        if !self.services.is_empty() {
+            writeln!(f, "services:")?;
+            for service in &self.services {
+                write!(f, "{}", service)?;
+            }
+        }
+        if !self.linkage.is_empty() {
+            writeln!(f, "linkage:")?;
+            for linkage in &self.linkage {
+                write!(f, "{}", linkage)?;
+            }
+        }
+        if let Some(reconcile) = &self.reconcile {
+            writeln!(f, "reconcile:")?;
+            write!(f, "{}", reconcile)?;
+        }
+        if !self.deprecations.is_empty() {
+            writeln!(f, "deprecations:")?;
+            for deprecation in &self.deprecations {
+                write!(f, "{}", deprecation)?;
+            }
+        }
+        if let Some(autoremove) = &self.autoremove {
+            writeln!(f, "autoremove:")?;
+            write!(f, "{}", autoremove)?;
+        }
+        if let Some(cleanup) = &self.cleanup {
+            writeln!(f, "cleanup:")?;
+            write!(f, "{}", cleanup)?;
+        }
+        if let Some(taps) = self.taps.as_ref().filter(|taps| **taps != TapReport::default()) {
+            writeln!(f, "taps:")?;
+            write!(f, "{}", taps)?;
+        }
+        Ok(())
+    }
+}
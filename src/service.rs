@@ -1,72 +1,1087 @@
-use anyhow::{Context, Result};
-use chrono::Duration;
-use tracing::info;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration as StdDuration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Duration, Local, Utc, Weekday};
+use futures::{StreamExt, stream};
+use tracing::{info, warn};
 
 use crate::{
-    brew_command::{BrewCommand, BrewError, CommandExecutor},
+    autoremove,
+    brew_command::{BrewCommand, BrewError, CommandExecutor, PackageKind},
+    brewfile::Brewfile,
+    caveats,
+    cleanup::{self, format_bytes},
+    config::{Config, PinRule, RunningProgramPolicy, SourceBuildPolicy, UpgradeMode},
+    dependencies::DependencyGraph,
+    deprecations::{self, Deprecation},
+    disk_space,
     formulae::{OutdatedPackages, Package},
+    health::{self, DoctorWarning, Impact},
+    history::{History, PinRecord, RollbackRecord},
+    info::InstalledInfo,
+    kegs, linkage,
+    lockfile::Lockfile,
+    parser, paths,
+    plan::UpgradePlan,
+    platform,
+    processes::{self, RunningProcess},
+    report::{
+        AutoremoveReport, CleanupReport, DeprecationReport, HealthReport, HeldPackage, LinkageReport, PackageReport, PinReport,
+        ReconcileReport, RunReport, ServiceReport, TapReport, UpgradeStatus,
+    },
+    services::BrewService,
+    snapshot::{self, Snapshot},
+    source_builds::{self, SourceBuildDecision},
+    taps::{self, TapInfo},
+    update::UpdateSummary,
 };
 
 pub struct BrewMaintainer<'b, E: CommandExecutor> {
     executor: &'b E,
+    config: Config,
+    process_lister: fn() -> Vec<RunningProcess>,
+    prefix: PathBuf,
+    free_space: fn(&Path) -> io::Result<u64>,
+}
+
+enum RunningCheck {
+    Proceed,
+    Defer,
+    Skip(String),
 }
 
 impl<'b, E: CommandExecutor> BrewMaintainer<'b, E> {
     pub fn new(executor: &'b E) -> Self {
-        Self { executor }
+        Self {
+            executor,
+            config: Config::default(),
+            // tests must not depend on what runs on the machine, they give their processes with `with_process_lister`
+            process_lister: if cfg!(test) { Vec::new } else { processes::running_processes },
+            prefix: paths::homebrew_prefix(),
+            free_space: disk_space::free_bytes,
+        }
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_process_lister(mut self, process_lister: fn() -> Vec<RunningProcess>) -> Self {
+        self.process_lister = process_lister;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_free_space(mut self, free_space: fn(&Path) -> io::Result<u64>) -> Self {
+        self.free_space = free_space;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_prefix(mut self, prefix: PathBuf) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Runs `brew doctor` and `brew config`, reporting only the warnings the previous run did not report, and
+    /// derives from all of them how the run must be aborted or degraded
+    pub fn health_check(&self, history: &mut History) -> Result<HealthReport, BrewError> {
+        // brew doctor exits with an error when it has warnings
+        let doctor = match self.executor.execute(&BrewCommand::Doctor { envs: self.executor.envs() }) {
+            Ok(output) | Err(BrewError::ExecutionFailed(output)) => output,
+            Err(e) => return Err(e),
+        };
+        let config = health::brew_config(&self.executor.execute(&BrewCommand::Config { envs: self.executor.envs() })?);
+        let warnings = health::doctor_warnings(&doctor);
+
+        let mut report = HealthReport::default();
+        let impacts = warnings.iter().map(|w| (w.impact(&self.prefix), w.title.clone())).chain(health::config_impacts(&config));
+        for (impact, problem) in impacts {
+            match impact {
+                Impact::Blocking => report.blocking.push(problem),
+                Impact::NoUpdate => {
+                    report.skip_update = true;
+                    report.degradations.push(format!("{}: not running brew update", problem));
+                }
+                Impact::NoSourceBuilds => {
+                    report.skip_source_builds = true;
+                    report.degradations.push(format!("{}: formulae without a bottle are not upgraded", problem));
+                }
+                Impact::None => {}
+            }
+        }
+        let titles: Vec<String> = warnings.iter().map(|w| w.title.clone()).collect();
+        let (known, new): (Vec<DoctorWarning>, Vec<DoctorWarning>) =
+            warnings.into_iter().partition(|w| history.doctor_warnings.contains(&w.title));
+        report.new_warnings = new;
+        report.known_warnings = known.len();
+        history.doctor_warnings = titles;
+        Ok(report)
     }
 
     pub fn update_reference_repositories(&self) -> Result<String, BrewError> {
         self.executor.execute(&BrewCommand::Update { envs: self.executor.envs() })
     }
 
+    /// Outdated formulae and casks, including the greedy casks selected in the configuration
     pub fn find_outdated_packages(&self) -> Result<OutdatedPackages, BrewError> {
-        let outdated_json = self.executor.execute(&BrewCommand::Outdated { envs: self.executor.envs() })?;
-        let output: OutdatedPackages = serde_json::from_str(outdated_json.as_str()).expect("error on parsing");
-        Ok(output)
+        let outdated_json = self.executor.execute(&BrewCommand::Outdated { greedy: None, envs: self.executor.envs() })?;
+        let mut outdated: OutdatedPackages = parser::parse_json(&outdated_json)?;
+        for (greedy, selected) in self.config.casks.selections() {
+            let greedy_json = self.executor.execute(&BrewCommand::Outdated { greedy: Some(greedy), envs: self.executor.envs() })?;
+            let greedy_outdated: OutdatedPackages = parser::parse_json(&greedy_json)?;
+            outdated.merge_casks(greedy_outdated.casks.into_iter().filter(|cask| selected.contains(&cask.name)));
+        }
+        Ok(outdated)
+    }
+
+    pub fn installed_info(&self) -> Result<InstalledInfo, BrewError> {
+        let info_json = self.executor.execute(&BrewCommand::Info { envs: self.executor.envs() })?;
+        parser::parse_json(&info_json)
+    }
+
+    /// Orders the outdated packages so that dependencies are upgraded before their dependents,
+    /// keeping brew's order when the dependency graph is not available
+    pub fn plan_upgrades<'a>(&self, outdated_packages: &'a OutdatedPackages, info: Option<&InstalledInfo>) -> UpgradePlan<'a> {
+        let packages: Vec<Package<'a>> = outdated_packages.iter().collect();
+        let Some(info) = info else {
+            return UpgradePlan::new(packages);
+        };
+        let graph = DependencyGraph::from_info(info);
+        let mut plan = UpgradePlan::new(graph.upgrade_order(&packages));
+        plan.dependencies = Some(graph.dependencies_among(&packages));
+        for cask in outdated_packages.casks.iter().filter_map(|c| info.cask(&c.name)) {
+            plan.cask_apps.insert(cask.token.clone(), cask.apps());
+        }
+        plan
+    }
+
+    /// Applies the source build policy to the planned formulae that have no bottle for this machine.
+    /// Returns a report for each package removed from the plan
+    pub fn avoid_source_builds(
+        &self, plan: &mut UpgradePlan, info: &InstalledInfo, bottle_tag: &str, today: Weekday,
+    ) -> Vec<PackageReport> {
+        let mut config = self.config.source_builds.clone();
+        if plan.source_builds_disabled {
+            config.policy = SourceBuildPolicy::Skip;
+        }
+        let mut avoided = vec![];
+        plan.packages.retain(|package| {
+            let Some(formula) = info.formula(package.name()) else {
+                return true;
+            };
+            match source_builds::decide(&config, formula, bottle_tag, today) {
+                SourceBuildDecision::Bottle => true,
+                SourceBuildDecision::Build(timeout) => {
+                    info!("{} will be built from source", package.name());
+                    plan.timeouts.insert((package.kind(), package.name().to_string()), timeout);
+                    true
+                }
+                SourceBuildDecision::Avoid(reason) => {
+                    info!("not upgrading {}: {}", package.name(), reason);
+                    avoided.push(PackageReport::new(package.name(), UpgradeStatus::Skipped(reason)).with_kind(package.kind()));
+                    false
+                }
+            }
+        });
+        avoided
+    }
+
+    /// Checks that the planned upgrades fit on disk, cleaning up first when they do not, and leaves the largest
+    /// packages out of the plan while they still do not fit. Returns a report for each package left out, and the
+    /// cleanup report when it ran
+    pub fn ensure_disk_space(
+        &self, plan: &mut UpgradePlan, info: Option<&InstalledInfo>, bottle_tag: Option<&str>, history: &mut History,
+    ) -> (Vec<PackageReport>, Option<CleanupReport>) {
+        let estimates: Vec<((PackageKind, &str), u64)> =
+            plan.packages.iter().map(|package| (package.key(), self.estimated_size(package, info, bottle_tag))).collect();
+        let needed: u64 = estimates.iter().map(|(_, size)| size).sum();
+        let mut available = match self.disk_space_budget() {
+            Ok(available) => available,
+            Err(e) => {
+                warn!("cannot check disk space, upgrading without checking it: {:#}", e);
+                return (vec![], None);
+            }
+        };
+        if needed <= available {
+            return (vec![], None);
+        }
+        info!("upgrades need about {} but {} is available, cleaning up first", format_bytes(needed), format_bytes(available));
+        let cleanup = self.cleanup(history).inspect_err(|e| warn!("cleanup before upgrading failed: {}", e)).ok();
+        available = self.disk_space_budget().unwrap_or(available);
+        let (_, left_out) = disk_space::fit(&estimates, available);
+        let reports = left_out
+            .iter()
+            .map(|key| {
+                let size = estimates.iter().find(|(k, _)| k == key).map_or(0, |(_, size)| *size);
+                let reason = format!("needs about {}, {} available", format_bytes(size), format_bytes(available));
+                PackageReport::new(key.1, UpgradeStatus::InsufficientDiskSpace(reason)).with_kind(key.0)
+            })
+            .collect();
+        plan.packages.retain(|package| !left_out.contains(&package.key()));
+        (reports, cleanup)
+    }
+
+    /// Space an upgrade needs: the bottle size when brew reports it, otherwise the disk usage of the installed version
+    fn estimated_size(&self, package: &Package, info: Option<&InstalledInfo>, bottle_tag: Option<&str>) -> u64 {
+        let bottle_size = match (package, info, bottle_tag) {
+            (Package::Formula(formula), Some(info), Some(tag)) => {
+                info.formula(&formula.name).and_then(|formula| formula.bottle_for(tag)).and_then(|file| file.size)
+            }
+            _ => None,
+        };
+        bottle_size.unwrap_or_else(|| match package {
+            Package::Formula(formula) => formula
+                .installed_versions
+                .last()
+                .map_or(0, |version| disk_space::dir_size(&self.prefix.join("Cellar").join(&formula.name).join(version))),
+            Package::Cask(cask) => disk_space::dir_size(&self.prefix.join("Caskroom").join(&cask.name)),
+        })
+    }
+
+    /// Space available to upgrades: the free space of the prefix volume, halved when the download cache shares it,
+    /// less the configured margin
+    fn disk_space_budget(&self) -> Result<u64> {
+        let cache = PathBuf::from(self.executor.execute(&BrewCommand::CachePath { envs: self.executor.envs() })?.trim());
+        let prefix_free = (self.free_space)(&self.prefix).with_context(|| format!("cannot stat {}", self.prefix.display()))?;
+        let available = if disk_space::same_volume(&self.prefix, &cache) {
+            prefix_free / 2
+        } else {
+            // the cache directory may not exist yet
+            (self.free_space)(&cache).map_or(prefix_free, |cache_free| prefix_free.min(cache_free))
+        };
+        Ok(available.saturating_sub(self.config.disk_space.margin_mb * 1024 * 1024))
+    }
+
+    /// Downloads the planned packages with bounded concurrency so that the upgrade phase is mostly local work.
+    /// Returns the packages ready to be upgraded and a report for each package whose download failed
+    pub async fn fetch_packages<'a>(&self, plan: &[Package<'a>]) -> (Vec<Package<'a>>, Vec<PackageReport>) {
+        let timeout = Duration::minutes(self.config.fetch.timeout_minutes as i64);
+        let results: Vec<(Package<'a>, Result<(), BrewError>)> = stream::iter(plan.iter().copied())
+            .map(|package| async move {
+                let cmd = BrewCommand::Fetch { package_name: package.name(), kind: package.kind(), envs: self.executor.envs() };
+                (package, self.executor.execute_with_timeout(&cmd, timeout).await.map(|_| ()))
+            })
+            .buffered(self.config.fetch.concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut fetched = vec![];
+        let mut failed = vec![];
+        for (package, result) in results {
+            match result {
+                Ok(_) => fetched.push(package),
+                Err(e) => {
+                    warn!("fetching {} failed: {}", package.name(), e);
+                    failed.push(
+                        PackageReport::new(package.name(), UpgradeStatus::FetchFailed(e.to_string())).with_kind(package.kind()),
+                    );
+                }
+            }
+        }
+        (fetched, failed)
+    }
+
+    pub async fn upgrade_packages_with_timeout(
+        &self, plan: &UpgradePlan<'_>, history: &mut History,
+    ) -> Result<Vec<PackageReport>, BrewError> {
+        let mut reports = vec![];
+        let mut still_outdated: Option<HashSet<(PackageKind, String)>> = None;
+        let mut queue: VecDeque<Package> = plan.packages.iter().copied().collect();
+        let mut deferred: HashSet<&str> = HashSet::new();
+        while let Some(package) = queue.pop_front() {
+            if still_outdated.as_ref().is_some_and(|outdated| !outdated.contains(&(package.kind(), package.name().to_string()))) {
+                info!("{} was already upgraded by a previous upgrade", package.name());
+                reports.push(PackageReport::new(package.name(), UpgradeStatus::UpgradedAsDependency).with_kind(package.kind()));
+                continue;
+            }
+            match self.check_running_program(plan, &package, !deferred.contains(package.name())) {
+                RunningCheck::Proceed => {}
+                RunningCheck::Defer => {
+                    deferred.insert(package.name());
+                    queue.push_back(package);
+                    continue;
+                }
+                RunningCheck::Skip(reason) => {
+                    reports.push(PackageReport::new(package.name(), UpgradeStatus::Skipped(reason)).with_kind(package.kind()));
+                    continue;
+                }
+            }
+            let timeout = plan.timeout_for(&package, &self.config.timeouts, history);
+            info!("upgrading {} with timeout {}", package.name(), timeout);
+            let started = Instant::now();
+            match self.executor.execute_with_timeout(&self.upgrade_command(&[package]), timeout).await {
+                Ok(output) => {
+                    history.record_duration(package.name(), started.elapsed().as_secs());
+                    let mut caveats = caveats::caveats(&output, &[package.name()]);
+                    reports.push(
+                        PackageReport::new(package.name(), UpgradeStatus::Upgraded)
+                            .with_kind(package.kind())
+                            .with_caveats(caveats.remove(package.name())),
+                    );
+                    // brew may have upgraded queued dependents or dependencies along the way
+                    if queue.iter().any(|queued| plan.may_upgrade_along(&package, queued)) {
+                        still_outdated = self.outdated_package_names();
+                    }
+                }
+                Err(e) => {
+                    reports.push(PackageReport::new(package.name(), UpgradeStatus::Failed(e.to_string())).with_kind(package.kind()))
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Upgrades the whole plan with a single brew invocation; when it fails the batch is split in halves
+    /// until the failing packages are isolated
+    pub async fn upgrade_packages_in_batch(
+        &self, plan: &UpgradePlan<'_>, history: &mut History,
+    ) -> Result<Vec<PackageReport>, BrewError> {
+        let mut reports = vec![];
+        let mut ready = vec![];
+        let mut deferred = vec![];
+        for package in &plan.packages {
+            match self.check_running_program(plan, package, true) {
+                RunningCheck::Proceed => ready.push(*package),
+                RunningCheck::Defer => deferred.push(*package),
+                RunningCheck::Skip(reason) => {
+                    reports.push(PackageReport::new(package.name(), UpgradeStatus::Skipped(reason)).with_kind(package.kind()))
+                }
+            }
+        }
+        reports.extend(self.upgrade_batches(&ready, plan, history).await);
+
+        let mut retried = vec![];
+        for package in deferred {
+            match self.check_running_program(plan, &package, false) {
+                RunningCheck::Skip(reason) => {
+                    reports.push(PackageReport::new(package.name(), UpgradeStatus::Skipped(reason)).with_kind(package.kind()))
+                }
+                _ => retried.push(package),
+            }
+        }
+        reports.extend(self.upgrade_batches(&retried, plan, history).await);
+
+        reports.sort_by_key(|r| plan.packages.iter().position(|p| p.key() == (r.kind, r.name.as_str())));
+        Ok(reports)
+    }
+
+    async fn upgrade_batches(&self, packages: &[Package<'_>], plan: &UpgradePlan<'_>, history: &mut History) -> Vec<PackageReport> {
+        let mut reports = vec![];
+        // a single brew invocation can only upgrade packages of the same kind with the same greedy flag
+        let mut batches: Vec<Vec<Package>> = vec![];
+        for package in packages {
+            match batches.iter_mut().find(|batch| self.same_upgrade_flags(&batch[0], package)) {
+                Some(batch) => batch.push(*package),
+                None => batches.push(vec![*package]),
+            }
+        }
+        batches.reverse();
+        while let Some(batch) = batches.pop() {
+            if batch.is_empty() {
+                continue;
+            }
+            let names: Vec<&str> = batch.iter().map(|p| p.name()).collect();
+            let timeout = plan.batch_timeout_for(&batch, &self.config.timeouts, history);
+            info!("upgrading {} package(s) in batch with timeout {}", names.len(), timeout);
+            let started = Instant::now();
+            let result = self.executor.execute_with_timeout(&self.upgrade_command(&batch), timeout).await;
+            match result {
+                Ok(output) => {
+                    if let [package] = batch.as_slice() {
+                        history.record_duration(package.name(), started.elapsed().as_secs());
+                    }
+                    let mut caveats = caveats::caveats(&output, &names);
+                    reports.extend(batch.iter().map(|package| {
+                        PackageReport::new(package.name(), UpgradeStatus::Upgraded)
+                            .with_kind(package.kind())
+                            .with_caveats(caveats.remove(package.name()))
+                    }));
+                }
+                Err(e) if batch.len() == 1 => {
+                    reports.push(PackageReport::new(names[0], UpgradeStatus::Failed(e.to_string())).with_kind(batch[0].kind()))
+                }
+                Err(e) => {
+                    warn!("batch of {} package(s) failed, bisecting: {}", batch.len(), e);
+                    // part of the batch may have been upgraded before the failure, with the caveats brew printed then
+                    let (remaining, upgraded): (Vec<Package>, Vec<Package>) = match self.outdated_package_names() {
+                        Some(outdated) => batch.iter().partition(|p| outdated.contains(&(p.kind(), p.name().to_string()))),
+                        None => (batch, vec![]),
+                    };
+                    let upgraded_names: Vec<&str> = upgraded.iter().map(|p| p.name()).collect();
+                    let mut caveats = match &e {
+                        BrewError::FailedWithOutput { output, .. } => caveats::caveats(output, &upgraded_names),
+                        _ => BTreeMap::new(),
+                    };
+                    reports.extend(upgraded.iter().map(|package| {
+                        PackageReport::new(package.name(), UpgradeStatus::Upgraded)
+                            .with_kind(package.kind())
+                            .with_caveats(caveats.remove(package.name()))
+                    }));
+                    let (first, second) = remaining.split_at(remaining.len().div_ceil(2));
+                    batches.push(second.to_vec());
+                    batches.push(first.to_vec());
+                }
+            }
+        }
+        reports
+    }
+
+    /// Looks for a running program of the package and applies the configured policy; deferring is only possible once.
+    /// Programs run by a brew service are not checked as the service is restarted after the upgrade
+    fn check_running_program(&self, plan: &UpgradePlan, package: &Package, can_defer: bool) -> RunningCheck {
+        let policy = self.config.running_programs.policy_for(package.name());
+        if policy == RunningProgramPolicy::Ignore || plan.restarted_services.contains(package.name()) {
+            return RunningCheck::Proceed;
+        }
+        let apps = plan.cask_apps.get(package.name()).map(Vec::as_slice).unwrap_or_default();
+        let roots = processes::package_roots(&self.prefix, package.name(), package.is_cask(), apps);
+        let running = (self.process_lister)();
+        let Some(process) = processes::find_running_under(&running, &roots) else {
+            return RunningCheck::Proceed;
+        };
+        let in_use = format!("in use by process {} ({})", process.pid, process.executable.display());
+        match policy {
+            RunningProgramPolicy::Defer if can_defer => {
+                info!("deferring {}: {}", package.name(), in_use);
+                RunningCheck::Defer
+            }
+            _ => {
+                info!("not upgrading {}: {}", package.name(), in_use);
+                RunningCheck::Skip(in_use)
+            }
+        }
+    }
+
+    /// `brew upgrade` for packages of the same kind, with the greedy flag configured for casks.
+    /// The replaced kegs are left to the cleanup phase when they must be retained for rollbacks
+    fn upgrade_command<'a>(&self, packages: &[Package<'a>]) -> BrewCommand<'a> {
+        let kind = packages[0].kind();
+        let greedy = if packages[0].is_cask() { self.config.casks.greedy_for(packages[0].name()) } else { None };
+        let mut envs = self.executor.envs();
+        if self.config.rollback.retention_days > 0 {
+            envs.insert("HOMEBREW_NO_INSTALL_CLEANUP", "1".to_string());
+        }
+        BrewCommand::Upgrade { package_names: packages.iter().map(|p| p.name()).collect(), kind, greedy, envs }
+    }
+
+    fn same_upgrade_flags(&self, a: &Package, b: &Package) -> bool {
+        a.kind() == b.kind() && (!a.is_cask() || self.config.casks.greedy_for(a.name()) == self.config.casks.greedy_for(b.name()))
+    }
+
+    /// Kinds and names of the packages brew still reports as outdated, if they can be read
+    fn outdated_package_names(&self) -> Option<HashSet<(PackageKind, String)>> {
+        self.find_outdated_packages()
+            .map(|outdated| outdated.iter().map(|p| (p.kind(), p.name().to_string())).collect())
+            .inspect_err(|e| warn!("cannot refresh outdated packages: {}", e))
+            .ok()
+    }
+
+    pub fn list_services(&self) -> Result<Vec<BrewService>, BrewError> {
+        let services_json = self.executor.execute(&BrewCommand::ServicesList { envs: self.executor.envs() })?;
+        parser::parse_json(&services_json)
     }
 
-    pub async fn upgrade_packages_with_timeout<'a>(
-        &self, outdated_packages: &'a OutdatedPackages, timeout: Duration,
-    ) -> Result<Vec<&'a Package>, BrewError> {
-        let mut failed_upgrade: Vec<&'a Package> = vec![];
-        for package in outdated_packages.iter() {
-            if let Err(_) = self
+    /// Restarts the services that were started before the upgrade phase and whose formula got upgraded,
+    /// then waits for each of them to be started again
+    pub async fn restart_services(&self, started: &[BrewService], upgraded: &[PackageReport]) -> Vec<ServiceReport> {
+        let mut reports = vec![];
+        for service in started.iter().filter(|s| upgraded.iter().any(|p| p.is_upgraded() && p.name == s.name)) {
+            info!("restarting service {}", service.name);
+            let timeout = Duration::seconds(self.config.services.restart_timeout_seconds as i64);
+            let restart = self
                 .executor
                 .execute_with_timeout(
-                    &BrewCommand::Upgrade { package_name: package.name.as_str(), envs: self.executor.envs() },
+                    &BrewCommand::ServicesRestart { service_name: &service.name, envs: self.executor.envs() },
                     timeout,
                 )
                 .await
+                .map_err(|e| e.to_string());
+            let result = match restart {
+                Ok(_) => self.wait_for_service_started(&service.name).await,
+                Err(e) => Err(e),
+            };
+            let (restarted, detail) = match result {
+                Ok(()) => (true, "started".to_string()),
+                Err(detail) => {
+                    warn!("service {} did not restart: {}", service.name, detail);
+                    (false, detail)
+                }
+            };
+            reports.push(ServiceReport { name: service.name.clone(), restarted, detail });
+        }
+        reports
+    }
+
+    async fn wait_for_service_started(&self, name: &str) -> Result<(), String> {
+        let attempts = self.config.services.verify_attempts.max(1);
+        let mut last_status = String::from("unknown");
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                tokio::time::sleep(StdDuration::from_secs(self.config.services.verify_interval_seconds)).await;
+            }
+            match self.list_services() {
+                Ok(services) => match services.iter().find(|s| s.name == name) {
+                    Some(service) if service.is_started() => return Ok(()),
+                    Some(service) => last_status = service.status.clone(),
+                    None => last_status = String::from("not listed"),
+                },
+                Err(e) => last_status = e.to_string(),
+            }
+        }
+        Err(format!("status is {} after {} check(s)", last_status, attempts))
+    }
+
+    /// Runs the configured smoke tests of the upgraded packages, marking the packages failing one as unhealthy
+    pub async fn run_smoke_tests(&self, reports: &mut [PackageReport]) {
+        for report in reports.iter_mut().filter(|r| r.is_upgraded()) {
+            for smoke_test in self.config.smoke_tests.get(&report.name).into_iter().flatten() {
+                info!("smoke testing {}: {}", report.name, smoke_test.command);
+                if let Err(failure) = smoke_test.run().await {
+                    warn!("{} is unhealthy: {}", report.name, failure);
+                    report.status = UpgradeStatus::Unhealthy(failure);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Tests the linkage of the installed dependents, direct or not, of the upgraded formulae, reinstalling the broken ones if configured
+    pub async fn verify_linkage(&self, upgraded_formulae: &[&str], history: &History) -> Vec<LinkageReport> {
+        let mut dependents: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for formula in upgraded_formulae {
+            match self.executor.execute(&BrewCommand::Uses { package_name: formula, envs: self.executor.envs() }) {
+                Ok(output) => {
+                    for dependent in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                        dependents.entry(dependent.to_string()).or_default().push(formula.to_string());
+                    }
+                }
+                Err(e) => warn!("cannot list dependents of {}: {}", formula, e),
+            }
+        }
+
+        let mut reports = vec![];
+        for (dependent, upgraded_dependencies) in dependents {
+            let broken = match self.executor.execute(&BrewCommand::Linkage { package_name: &dependent, envs: self.executor.envs() })
             {
-                failed_upgrade.push(package);
+                Ok(_) => continue,
+                Err(BrewError::ExecutionFailed(output)) => linkage::broken_libraries(&output),
+                Err(e) => vec![e.to_string()],
+            };
+            warn!("{} has broken linkage after upgrading {}", dependent, upgraded_dependencies.join(", "));
+            let reinstall = if self.config.linkage.reinstall_broken {
+                let timeout = self.config.timeouts.timeout_for(&dependent, history);
+                let cmd = BrewCommand::Reinstall { package_name: &dependent, envs: self.executor.envs() };
+                Some(self.executor.execute_with_timeout(&cmd, timeout).await.map(|_| ()).map_err(|e| e.to_string()))
+            } else {
+                None
+            };
+            reports.push(LinkageReport { name: dependent, upgraded_dependencies, broken, reinstall });
+        }
+        reports
+    }
+
+    /// Brewfile and installed versions, reusing the installed info when it was already read
+    pub fn snapshot(&self, label: &str, info: Option<&InstalledInfo>) -> Result<Snapshot, BrewError> {
+        let brewfile = self.executor.execute(&BrewCommand::BundleDump { envs: self.executor.envs() })?;
+        let fresh_info;
+        let info = match info {
+            Some(info) => info,
+            None => {
+                fresh_info = self.installed_info()?;
+                &fresh_info
+            }
+        };
+        Ok(Snapshot::new(label, brewfile, info, Utc::now()))
+    }
+
+    /// Converges the machine to the desired Brewfile: `brew bundle install` when `brew bundle check` is not satisfied,
+    /// then, when configured, uninstalls the leaves the Brewfile does not declare
+    pub async fn reconcile(&self, brewfile_path: &Path) -> Result<ReconcileReport> {
+        let config = &self.config.reconcile;
+        let brewfile = brewfile_path.to_str().context("the Brewfile path is not valid UTF-8")?;
+        let content =
+            fs::read_to_string(brewfile_path).with_context(|| format!("cannot read Brewfile {}", brewfile_path.display()))?;
+        let taps = self.output_lines(&BrewCommand::Taps { envs: self.executor.envs() })?;
+        let leaves = self.output_lines(&BrewCommand::Leaves { envs: self.executor.envs() })?;
+        let info = self.installed_info()?;
+        let diff = Brewfile::parse(&content).diff(&taps, &info, &leaves);
+        let mut report = ReconcileReport { diff, report_only: config.report_only, install: None, uninstalled: vec![] };
+        if config.report_only {
+            return Ok(report);
+        }
+        if self.executor.execute(&BrewCommand::BundleCheck { brewfile, envs: self.executor.envs() }).is_err() {
+            let timeout = Duration::minutes(config.install_timeout_minutes as i64);
+            let install = BrewCommand::BundleInstall { brewfile, envs: self.executor.envs() };
+            report.install =
+                Some(self.executor.execute_with_timeout(&install, timeout).await.map(|_| ()).map_err(|e| e.to_string()));
+        }
+        // leaves the failed install was meant to declare could be uninstalled by mistake, wait for the next run
+        if config.uninstall_unlisted_leaves && !matches!(report.install, Some(Err(_))) {
+            for leaf in &report.diff.unlisted_leaves {
+                let uninstall =
+                    BrewCommand::Uninstall { package_name: leaf, kind: PackageKind::Formula, envs: self.executor.envs() };
+                let outcome = self.executor.execute(&uninstall).map(|_| ()).map_err(|e| e.to_string());
+                report.uninstalled.push((leaf.clone(), outcome));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Non-empty lines printed by a listing command such as `brew tap`
+    fn output_lines(&self, cmd: &BrewCommand) -> Result<Vec<String>, BrewError> {
+        let output = self.executor.execute(cmd)?;
+        Ok(output.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect())
+    }
+
+    /// Replaces the deprecated or disabled packages declaring a replacement when migrations are enabled: installs the
+    /// replacement, then uninstalls the package
+    pub async fn migrate_deprecated(&self, deprecations: Vec<Deprecation>, history: &History) -> Vec<DeprecationReport> {
+        let mut reports = vec![];
+        for deprecation in deprecations {
+            let migration = match &deprecation.replacement {
+                Some((replacement, kind)) if self.config.deprecations.migrate => {
+                    info!("migrating {} to {}", deprecation.name, replacement);
+                    Some(self.migrate(&deprecation, replacement, *kind, history).await.map_err(|e| e.to_string()))
+                }
+                _ => None,
+            };
+            reports.push(DeprecationReport { deprecation, migration });
+        }
+        reports
+    }
+
+    async fn migrate(
+        &self, deprecation: &Deprecation, replacement: &str, kind: PackageKind, history: &History,
+    ) -> Result<(), BrewError> {
+        let install = BrewCommand::Install { package_name: replacement, kind, envs: self.executor.envs() };
+        self.executor.execute_with_timeout(&install, self.config.timeouts.timeout_for(replacement, history)).await?;
+        let uninstall =
+            BrewCommand::Uninstall { package_name: &deprecation.name, kind: deprecation.kind, envs: self.executor.envs() };
+        self.executor.execute(&uninstall).map(|_| ())
+    }
+
+    /// Uninstalls the orphaned dependencies listed by `brew autoremove --dry-run` except the protected ones and the
+    /// orphans they depend on, dependents first, using
+    /// `brew autoremove` itself when none is protected, and records them in history
+    pub fn autoremove(&self, info: Option<&InstalledInfo>, history: &mut History) -> AutoremoveReport {
+        let mut report = AutoremoveReport::default();
+        let preview = match self.executor.execute(&BrewCommand::Autoremove { dry_run: true, envs: self.executor.envs() }) {
+            Ok(output) => autoremove::orphans(&output),
+            Err(e) => {
+                report.error = Some(e.to_string());
+                return report;
+            }
+        };
+        let (mut protected, mut removable): (Vec<String>, Vec<String>) =
+            preview.into_iter().partition(|name| self.config.autoremove.protect.contains(name));
+        if let Some(info) = info {
+            // the orphans a protected formula depends on are protected along with it
+            let graph = DependencyGraph::from_info(info);
+            let needed = graph.dependencies_of(&protected);
+            let (kept, rest): (Vec<String>, Vec<String>) = removable.into_iter().partition(|name| needed.contains(name.as_str()));
+            protected.extend(kept);
+            // brew refuses to uninstall an orphan another installed orphan still depends on
+            removable = graph.removal_order(rest);
+        }
+        info!("autoremoving {:?}, protected {:?}", removable, protected);
+        if protected.is_empty() && !removable.is_empty() {
+            match self.executor.execute(&BrewCommand::Autoremove { dry_run: false, envs: self.executor.envs() }) {
+                Ok(_) => report.removed = removable,
+                Err(e) => report.error = Some(e.to_string()),
+            }
+        } else {
+            for name in removable {
+                match self.executor.execute(&BrewCommand::Uninstall {
+                    package_name: &name,
+                    kind: PackageKind::Formula,
+                    envs: self.executor.envs(),
+                }) {
+                    Ok(_) => report.removed.push(name),
+                    Err(e) => report.failed.push((name, e.to_string())),
+                }
             }
         }
-        Ok(failed_upgrade)
+        let now = Utc::now();
+        for name in &report.removed {
+            history.record_autoremoved(name, info.and_then(|info| info.formula(name)).and_then(|f| f.installed_version()), now);
+        }
+        report.protected = protected;
+        report
     }
 
-    pub fn cleanup(&self) -> Result<String, BrewError> {
-        self.executor.execute(&BrewCommand::Cleanup { envs: self.executor.envs() })
+    /// Records the keg replaced by each upgraded formula and forgets the ones past the retention period
+    pub fn retain_previous_kegs(&self, outdated: &OutdatedPackages, reports: &[PackageReport], history: &mut History) {
+        let now = Utc::now();
+        if self.config.rollback.retention_days > 0 {
+            for report in reports.iter().filter(|r| r.is_upgraded()) {
+                let formula = outdated.formulae.iter().find(|f| f.name == report.name);
+                if let Some(previous) = formula.and_then(|f| f.installed_versions.last()) {
+                    history.retain_keg(&report.name, previous, now);
+                }
+            }
+        }
+        history.expire_retained_kegs(self.config.rollback.retention_days, now);
+    }
+
+    /// `brew cleanup` with the configured policy, sparing the formulae whose previous keg is still retained or whose
+    /// previous versions are kept. Formulae spared for a retained keg keep all their kegs until the retention expires;
+    /// the `keep_versions` formulae keep their most recent previous kegs and the older ones are removed here
+    pub fn cleanup(&self, history: &mut History) -> Result<CleanupReport, BrewError> {
+        let policy = &self.config.cleanup;
+        let spared: BTreeSet<&str> = history
+            .retained_kegs
+            .iter()
+            .map(|keg| keg.name.as_str())
+            .chain(policy.keep_versions.keys().map(String::as_str))
+            .collect();
+        let mut envs = self.executor.envs();
+        if !spared.is_empty() {
+            envs.insert("HOMEBREW_NO_CLEANUP_FORMULAE", spared.into_iter().collect::<Vec<_>>().join(","));
+        }
+        let output = self.executor.execute(&BrewCommand::Cleanup { prune: policy.prune.as_deref(), scrub: policy.scrub, envs })?;
+        info!("output: {}", output);
+        let mut report = CleanupReport { reclaimed_bytes: cleanup::reclaimed_bytes(&output), ..Default::default() };
+
+        let mut kept: Vec<(&String, &usize)> = policy.keep_versions.iter().collect();
+        kept.sort();
+        for (name, keep) in kept {
+            let retained = history.retained_keg(name).map(|keg| keg.version.as_str());
+            for (version, outcome) in kegs::remove_previous_kegs(&self.prefix, name, *keep, retained) {
+                match outcome {
+                    Ok(()) => report.removed_kegs.push(format!("{} {}", name, version)),
+                    Err(e) => report.errors.push(format!("cannot remove {} {}: {}", name, version, e)),
+                }
+            }
+        }
+        history.reclaimed_bytes += report.reclaimed_bytes.unwrap_or_default();
+        report.total_reclaimed_bytes = history.reclaimed_bytes;
+        Ok(report)
+    }
+
+    /// Links back the keg replaced by the last upgrade of the formula, or the most recent other installed keg,
+    /// and pins the formula at that keg so that the next run does not upgrade it again
+    pub fn rollback(&self, name: &str, history: &mut History) -> Result<RollbackRecord> {
+        let current = kegs::linked_version(&self.prefix, name).with_context(|| format!("{} is not installed", name))?;
+        let installed = kegs::installed_versions(&self.prefix, name);
+        let previous = history
+            .retained_keg(name)
+            .map(|keg| keg.version.clone())
+            .filter(|version| installed.contains(version) && *version != current)
+            .or_else(|| installed.into_iter().find(|version| *version != current))
+            .with_context(|| format!("no previous keg of {} is installed", name))?;
+        info!("rolling back {} from {} to {}", name, current, previous);
+
+        self.executor.execute(&BrewCommand::Unlink { package_name: name, envs: self.executor.envs() })?;
+        let switched = kegs::point_opt_to(&self.prefix, name, &previous)
+            .with_context(|| format!("cannot select keg {} of {}", previous, name))
+            .and_then(|()| Ok(self.executor.execute(&BrewCommand::Link { package_name: name, envs: self.executor.envs() })?));
+        if let Err(e) = switched {
+            warn!("cannot link {} {}, linking {} back", name, previous, current);
+            kegs::point_opt_to(&self.prefix, name, &current)?;
+            self.executor.execute(&BrewCommand::Link { package_name: name, envs: self.executor.envs() })?;
+            return Err(e);
+        }
+        self.pin(name, None, history)?;
+        kegs::point_pin_to(&self.prefix, name, &previous).with_context(|| format!("cannot pin keg {} of {}", previous, name))?;
+
+        let record =
+            RollbackRecord { name: name.to_string(), from_version: current, to_version: previous, rolled_back_at: Utc::now() };
+        history.retained_kegs.retain(|keg| keg.name != name);
+        history.rollbacks.push(record.clone());
+        Ok(record)
+    }
+
+    /// Flags the taps `brew update` failed to fetch and the third-party taps not on the trust list or that no installed
+    /// package comes from; with `untap_unused` the unused ones are untapped, unless the desired Brewfile declares them
+    pub fn audit_taps(&self, info: &InstalledInfo, update_output: Option<&str>) -> Result<TapReport, BrewError> {
+        let output = self.executor.execute(&BrewCommand::TapInfo { envs: self.executor.envs() })?;
+        let installed_taps: Vec<TapInfo> = parser::parse_json(&output)?;
+        let declared = match self.config.reconcile.desired_brewfile(platform::short_host_name().as_deref()) {
+            Ok(Some(content)) => Brewfile::parse(&content).taps,
+            _ => BTreeSet::new(),
+        };
+        let mut report =
+            TapReport { failed_updates: update_output.map(taps::failed_updates).unwrap_or_default(), ..TapReport::default() };
+        for tap in installed_taps.iter().filter(|tap| !tap.official) {
+            if !taps::is_trusted(&tap.name, &self.config.taps.trusted) {
+                report.untrusted.push((tap.name.clone(), tap.remote.clone()));
+            }
+            if tap.is_used(info) {
+                continue;
+            }
+            report.unused.push(tap.name.clone());
+            if self.config.taps.untap_unused && !declared.contains(&tap.name) {
+                match self.executor.execute(&BrewCommand::Untap { tap: &tap.name, envs: self.executor.envs() }) {
+                    Ok(_) => report.untapped.push(tap.name.clone()),
+                    Err(e) => report.errors.push(format!("cannot untap {}: {}", tap.name, e)),
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Pins the formula, for good or until `expires_at` when the next run unpins it
+    pub fn pin(&self, name: &str, expires_at: Option<DateTime<Utc>>, history: &mut History) -> Result<(), BrewError> {
+        self.executor.execute(&BrewCommand::Pin { package_name: name, envs: self.executor.envs() })?;
+        history.record_pin(PinRecord { name: name.to_string(), pinned_at: Utc::now(), expires_at, configured: false });
+        Ok(())
+    }
+
+    pub fn unpin(&self, name: &str, history: &mut History) -> Result<(), BrewError> {
+        self.executor.execute(&BrewCommand::Unpin { package_name: name, envs: self.executor.envs() })?;
+        history.forget_pin(name);
+        Ok(())
+    }
+
+    /// Pins the configured formulae not pinned yet and unpins the pins made by the maintainer that expired or are no
+    /// longer configured; pins made by hand are left alone. Reports every pinned formula with the version it is kept from
+    pub fn enforce_pins(
+        &self, outdated: &OutdatedPackages, info: Option<&InstalledInfo>, history: &mut History, now: DateTime<Utc>,
+    ) -> Result<PinReport, BrewError> {
+        let output = self.executor.execute(&BrewCommand::ListPinned { envs: self.executor.envs() })?;
+        let mut pinned: BTreeSet<String> = output.split_whitespace().map(String::from).collect();
+        // pins removed by hand are forgotten
+        history.pins.retain(|pin| pinned.contains(&pin.name));
+
+        let mut report = PinReport::default();
+        let rules: BTreeMap<&String, &PinRule> = self.config.pins.iter().collect();
+        for (name, rule) in rules.into_iter().filter(|(_, rule)| rule.expires_at().is_none_or(|expires_at| expires_at > now)) {
+            if !pinned.contains(name) {
+                if let Err(e) = self.executor.execute(&BrewCommand::Pin { package_name: name, envs: self.executor.envs() }) {
+                    report.errors.push(format!("cannot pin {}: {}", name, e));
+                    continue;
+                }
+                pinned.insert(name.clone());
+                report.pinned.push(name.clone());
+            } else if !history.pin(name).is_some_and(|pin| pin.configured) {
+                // pinned by hand: its expiry stays and it is not released with the rule
+                continue;
+            }
+            history.record_pin(PinRecord { name: name.clone(), pinned_at: now, expires_at: rule.expires_at(), configured: true });
+        }
+
+        // a pin both expired and no longer configured is released once, as expired
+        let mut released: BTreeMap<String, &str> =
+            history.expired_pins(now).into_iter().map(|name| (name, "pin expired")).collect();
+        for pin in history.pins.iter().filter(|pin| pin.configured && !self.config.pins.contains_key(&pin.name)) {
+            released.entry(pin.name.clone()).or_insert("no longer configured");
+        }
+        for (name, reason) in released {
+            match self.unpin(&name, history) {
+                Ok(()) => {
+                    pinned.remove(&name);
+                    report.unpinned.push((name, reason.to_string()));
+                }
+                Err(e) => report.errors.push(format!("cannot unpin {}: {}", name, e)),
+            }
+        }
+
+        report.held = pinned
+            .into_iter()
+            .map(|name| {
+                let formula = outdated.formulae.iter().find(|f| f.name == name);
+                let version = formula
+                    .and_then(|f| f.pinned_version.clone())
+                    .or_else(|| info.and_then(|info| info.formula(&name)).and_then(|f| f.installed_version()));
+                HeldPackage {
+                    version,
+                    available: formula.map(|f| f.current_version.clone()),
+                    expires_at: history.pin(&name).and_then(|pin| pin.expires_at),
+                    name,
+                }
+            })
+            .collect();
+        Ok(report)
     }
 }
 
-pub async fn run_maintenance<'a, E: CommandExecutor>(brew_maintainer: &BrewMaintainer<'a, E>) -> Result<()> {
-    let output = brew_maintainer.update_reference_repositories().context("\u{274c} Failed to update reference repositories")?;
-    info!("output: {}", output);
-    info!("\u{2705} brew update done");
+pub async fn run_maintenance<'a, E: CommandExecutor>(
+    brew_maintainer: &BrewMaintainer<'a, E>, history: &mut History,
+) -> Result<RunReport> {
+    let mut report = RunReport::default();
+    if brew_maintainer.config.health.check {
+        match brew_maintainer.health_check(history) {
+            Ok(health) if !health.blocking.is_empty() => {
+                bail!("\u{274c} Pre-flight health check failed: {}", health.blocking.join(", "))
+            }
+            Ok(health) => {
+                info!("\u{2705} brew doctor done, {} new warnings", health.new_warnings.len());
+                report.health = Some(health);
+            }
+            Err(e) => warn!("cannot run the pre-flight health check: {}", e),
+        }
+    }
+    let mut update_output = None;
+    if report.health.as_ref().is_some_and(|health| health.skip_update) {
+        warn!("skipping brew update, upgrading with the current metadata");
+    } else {
+        let output = match brew_maintainer.update_reference_repositories() {
+            Ok(output) => output,
+            // brew update fails when a tap cannot be fetched, after updating the others
+            Err(BrewError::ExecutionFailed(output)) if !taps::failed_updates(&output).is_empty() => {
+                warn!("some taps failed to update: {}", taps::failed_updates(&output).join(", "));
+                output
+            }
+            Err(e) => return Err(e).context("\u{274c} Failed to update reference repositories"),
+        };
+        info!("output: {}", output);
+        report.update = Some(UpdateSummary::parse(&output));
+        update_output = Some(output);
+        info!("\u{2705} brew update done");
+    }
     let outdated_packages = brew_maintainer.find_outdated_packages().context("\u{274c} Failed in finding outdated packages")?;
     info!("outdated:packages: \n{}", outdated_packages);
     info!("\u{2705} brew outdated done");
-    let failed_packages = brew_maintainer
-        .upgrade_packages_with_timeout(&outdated_packages, Duration::minutes(5))
-        .await
-        .context("\u{274c} Failure occurred while upgrading packages")?;
-    info!("failed upgrade: {:?}", failed_packages);
+    let info = brew_maintainer.installed_info().inspect_err(|e| warn!("cannot read installed packages info: {}", e)).ok();
+    let deprecated = info.as_ref().map(deprecations::find).unwrap_or_default();
+    if !deprecated.is_empty() {
+        warn!("{} installed packages are deprecated or disabled", deprecated.len());
+    }
+    if let (Some(update), Some(info)) = (report.update.as_mut(), info.as_ref()) {
+        update.highlight_installed(info);
+    }
+    save_snapshot(brew_maintainer, "before", info.as_ref());
+    match brew_maintainer.enforce_pins(&outdated_packages, info.as_ref(), history, Utc::now()) {
+        Ok(pins) => {
+            info!("\u{2705} pins enforced, {} pinned packages", pins.held.len());
+            report.pins = Some(pins);
+        }
+        Err(e) => warn!("cannot enforce pins: {}", e),
+    }
+    let mut plan = brew_maintainer.plan_upgrades(&outdated_packages, info.as_ref());
+    if let Some(pins) = &report.pins {
+        // brew refuses to upgrade pinned formulae, they are reported in the pins section instead
+        plan.packages.retain(|package| package.kind() != PackageKind::Formula || !pins.holds(package.name()));
+    }
+    plan.source_builds_disabled = report.health.as_ref().is_some_and(|health| health.skip_source_builds);
+    let bottle_tag = platform::bottle_tag();
+    match (info.as_ref(), bottle_tag.as_deref()) {
+        (Some(info), Some(tag)) => {
+            let avoided = brew_maintainer.avoid_source_builds(&mut plan, info, tag, Local::now().weekday());
+            report.packages.extend(avoided);
+        }
+        _ => warn!("cannot predict source builds, upgrading without source build policy"),
+    }
+    if brew_maintainer.config.disk_space.check {
+        let (left_out, cleanup) = brew_maintainer.ensure_disk_space(&mut plan, info.as_ref(), bottle_tag.as_deref(), history);
+        report.cleanup = cleanup;
+        info!("\u{2705} disk space check done, {} left out", left_out.len());
+        report.packages.extend(left_out);
+    }
+    if brew_maintainer.config.fetch.enabled {
+        let (fetched, failed) = brew_maintainer.fetch_packages(&plan.packages).await;
+        info!("\u{2705} brew fetch done, {} failed", failed.len());
+        report.packages.extend(failed);
+        plan.packages = fetched;
+    }
+    let started_services = if brew_maintainer.config.services.restart {
+        let services =
+            brew_maintainer.list_services().inspect_err(|e| warn!("cannot list brew services: {}", e)).unwrap_or_default();
+        services.into_iter().filter(|s| s.is_started()).collect()
+    } else {
+        vec![]
+    };
+    plan.restarted_services = started_services.iter().map(|s| s.name.clone()).collect();
+    let upgraded = match brew_maintainer.config.upgrade.mode {
+        UpgradeMode::Serial => brew_maintainer.upgrade_packages_with_timeout(&plan, history).await,
+        UpgradeMode::Batch => brew_maintainer.upgrade_packages_in_batch(&plan, history).await,
+    };
+    report.packages.extend(upgraded.context("\u{274c} Failure occurred while upgrading packages")?);
+    info!("failed upgrade: {:?}", report.failed().map(|p| p.name.as_str()).collect::<Vec<_>>());
     info!("\u{2705} brew upgrade done");
-    let output = brew_maintainer.cleanup().context("\u{274c} Failed to cleanup")?;
-    info!("output: {}", output);
+    report.services = brew_maintainer.restart_services(&started_services, &report.packages).await;
+    if !report.services.is_empty() {
+        info!("\u{2705} brew services restart done");
+    }
+    brew_maintainer.run_smoke_tests(&mut report.packages).await;
+    if brew_maintainer.config.linkage.verify {
+        let upgraded_formulae: Vec<&str> = report
+            .packages
+            .iter()
+            .filter(|p| p.is_upgraded() && outdated_packages.formulae.iter().any(|f| f.name == p.name))
+            .map(|p| p.name.as_str())
+            .collect();
+        report.linkage = brew_maintainer.verify_linkage(&upgraded_formulae, history).await;
+        info!("\u{2705} brew linkage done, {} broken", report.linkage.len());
+    }
+    report.reconcile = reconcile_brewfile(brew_maintainer).await;
+    report.deprecations = brew_maintainer.migrate_deprecated(deprecated, history).await;
+    if brew_maintainer.config.autoremove.enabled {
+        let autoremoved = brew_maintainer.autoremove(info.as_ref(), history);
+        info!("\u{2705} brew autoremove done, {} removed", autoremoved.removed.len());
+        report.autoremove = Some(autoremoved);
+    }
+    brew_maintainer.retain_previous_kegs(&outdated_packages, &report.packages, history);
+    let cleanup = brew_maintainer.cleanup(history).context("\u{274c} Failed to cleanup")?;
+    report.cleanup = Some(match report.cleanup.take() {
+        Some(before_upgrading) => before_upgrading.merge(cleanup),
+        None => cleanup,
+    });
     info!("\u{2705} brew cleanup done");
-    Ok(())
+    let installed_after =
+        brew_maintainer.installed_info().inspect_err(|e| warn!("cannot read installed packages info: {}", e)).ok();
+    if let Some(info) = installed_after.as_ref().filter(|_| brew_maintainer.config.taps.audit) {
+        match brew_maintainer.audit_taps(info, update_output.as_deref()) {
+            Ok(taps) => {
+                info!("\u{2705} tap audit done, {} unused, {} untrusted", taps.unused.len(), taps.untrusted.len());
+                report.taps = Some(taps);
+            }
+            Err(e) => warn!("cannot audit taps: {}", e),
+        }
+    }
+    save_snapshot(brew_maintainer, "after", installed_after.as_ref());
+    if let Some(info) = &installed_after {
+        let lockfile = Lockfile::new(info, platform::short_host_name(), Utc::now());
+        match lockfile.save(&paths::lockfile()) {
+            Ok(()) => info!("\u{2705} lockfile written to {}", paths::lockfile().display()),
+            Err(e) => warn!("cannot write lockfile: {:#}", e),
+        }
+    }
+    Ok(report)
+}
+
+/// Writes the desired Brewfile of this host to the state directory and converges to it, when a Brewfile is configured
+async fn reconcile_brewfile<E: CommandExecutor>(brew_maintainer: &BrewMaintainer<'_, E>) -> Option<ReconcileReport> {
+    let content = brew_maintainer
+        .config
+        .reconcile
+        .desired_brewfile(platform::short_host_name().as_deref())
+        .inspect_err(|e| warn!("cannot reconcile with the Brewfile: {:#}", e))
+        .ok()??;
+    let path = paths::desired_brewfile();
+    let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|()| fs::write(&path, content));
+    if let Err(e) = written {
+        warn!("cannot write {}: {}", path.display(), e);
+        return None;
+    }
+    let reconciled = brew_maintainer.reconcile(&path).await.inspect_err(|e| warn!("cannot reconcile with the Brewfile: {:#}", e));
+    info!("\u{2705} brew bundle reconcile done");
+    reconciled.ok()
+}
+
+/// Saves a snapshot in the state directory, a failure only costs the ability to diff this run
+fn save_snapshot<E: CommandExecutor>(brew_maintainer: &BrewMaintainer<'_, E>, label: &str, info: Option<&InstalledInfo>) {
+    if !brew_maintainer.config.snapshots.enabled {
+        return;
+    }
+    let dir = paths::snapshot_dir();
+    let saved = brew_maintainer.snapshot(label, info).map_err(anyhow::Error::from).and_then(|snapshot| {
+        let path = snapshot.save(&dir)?;
+        snapshot::prune(&dir, brew_maintainer.config.snapshots.keep)?;
+        Ok(path)
+    });
+    match saved {
+        Ok(path) => info!("snapshot {} saved to {}", label, path.display()),
+        Err(e) => warn!("cannot save snapshot {}: {:#}", label, e),
+    }
 }
 
 #[cfg(test)]
@@ -75,12 +1090,13 @@ mod tests {
     use std::{
         collections::HashMap,
         sync::{Arc, Mutex},
-        time::Duration as StdDuration,
     };
 
     use crate::{
         brew_command::{BrewCommand, BrewError, CommandExecutor},
+        config::{CaskConfig, FetchConfig, PinRule, ServicesConfig, SourceBuildConfig, SourceBuildPolicy},
         service::BrewMaintainer,
+        smoke_tests::SmokeTest,
     };
 
     #[test]
@@ -107,13 +1123,834 @@ mod tests {
         mock.assert_command_called(&["update"]);
     }
 
+    const OUTDATED_JSON: &str = r#"{
+        "formulae": [
+            {"name": "llvm", "installed_versions": ["20.1.8"], "current_version": "21.1.0", "pinned": false, "pinned_version": null},
+            {"name": "jq", "installed_versions": ["1.7.1"], "current_version": "1.8.0", "pinned": false, "pinned_version": null}
+        ],
+        "casks": []
+    }"#;
+
+    fn outdated_packages() -> OutdatedPackages {
+        serde_json::from_str(OUTDATED_JSON).unwrap()
+    }
+
+    const JQ_OUTDATED_JSON: &str = r#"{
+        "formulae": [
+            {"name": "jq", "installed_versions": ["1.7.1"], "current_version": "1.8.0", "pinned": false, "pinned_version": null}
+        ],
+        "casks": []
+    }"#;
+
+    const NOTHING_OUTDATED_JSON: &str = r#"{"formulae": [], "casks": []}"#;
+
+    #[test]
+    fn should_fail_with_parse_error_instead_of_panicking_on_unexpected_output() {
+        let mock = MockBrewCommand::new().with_execute_response(Ok("Error: Your Command Line Tools are too outdated.".to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = system_under_test.find_outdated_packages();
+        assert!(matches!(outdated, Err(BrewError::ParseFailed { .. })));
+    }
+
+    #[tokio::test]
+    async fn should_upgrade_each_package_with_its_own_timeout() {
+        let mut config = Config::default();
+        config.timeouts.overrides.insert("llvm".to_string(), 300);
+        let mock = MockBrewCommand::new().with_execute_response(Ok(JQ_OUTDATED_JSON.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let mut history = History::default();
+        let reports = system_under_test.upgrade_packages_with_timeout(&plan, &mut history).await.unwrap();
+        assert!(reports.iter().all(|r| r.status == UpgradeStatus::Upgraded));
+        let captured = mock.get_captured_commands();
+        assert_eq!(captured[0].args, vec!["upgrade", "--formula", "llvm"]);
+        assert_eq!(captured[0].timeout, Some(Duration::minutes(300)));
+        assert_eq!(captured[2].args, vec!["upgrade", "--formula", "jq"]);
+        assert_eq!(captured[2].timeout, Some(Duration::minutes(5)));
+        assert!(captured.iter().all(|cmd| cmd.command == "brew" && cmd.envs["HOME"] == "/mock/home"));
+    }
+
+    #[tokio::test]
+    async fn should_record_durations_only_for_successful_upgrades() {
+        let mock = MockBrewCommand::new()
+            .with_timeout_response(Err(BrewError::Timeout))
+            .with_timeout_response(Ok(String::new()))
+            .with_delay(StdDuration::from_millis(10));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let mut history = History::default();
+        let reports = system_under_test.upgrade_packages_with_timeout(&plan, &mut history).await.unwrap();
+        assert_eq!(reports.iter().filter(|r| r.is_failed()).map(|r| r.name.as_str()).collect::<Vec<_>>(), vec!["llvm"]);
+        assert!(!history.durations.contains_key("llvm"));
+        assert_eq!(history.durations["jq"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_skip_packages_already_upgraded_as_side_effect() {
+        let mock = MockBrewCommand::new().with_execute_response(Ok(NOTHING_OUTDATED_JSON.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_with_timeout(&plan, &mut History::default()).await.unwrap();
+        assert_eq!(reports[0], PackageReport::new("llvm", UpgradeStatus::Upgraded));
+        assert_eq!(reports[1], PackageReport::new("jq", UpgradeStatus::UpgradedAsDependency));
+        mock.assert_call_count(2);
+        mock.assert_command_called(&["outdated", "--json=v2"]);
+    }
+
+    #[tokio::test]
+    async fn should_upgrade_whole_plan_in_a_single_batch_when_it_succeeds() {
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        assert!(reports.iter().all(|r| r.status == UpgradeStatus::Upgraded));
+        mock.assert_call_count(1);
+        mock.assert_command_called(&["upgrade", "--formula", "llvm", "jq"]);
+        assert_eq!(mock.get_captured_commands()[0].timeout, Some(Duration::minutes(10)));
+    }
+
+    #[tokio::test]
+    async fn should_bisect_failed_batch_to_isolate_failing_package() {
+        let mock = MockBrewCommand::new()
+            .with_timeout_response(Err(BrewError::ExecutionFailed("exit 1".to_string())))
+            .with_timeout_response(Err(BrewError::ExecutionFailed("exit 1".to_string())))
+            .with_execute_response(Ok(OUTDATED_JSON.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        assert!(reports[0].is_failed());
+        assert_eq!(reports[1], PackageReport::new("jq", UpgradeStatus::Upgraded));
+        let args: Vec<Vec<String>> = mock.get_captured_commands().into_iter().map(|c| c.args).collect();
+        assert_eq!(
+            args,
+            vec![
+                vec!["upgrade", "--formula", "llvm", "jq"],
+                vec!["outdated", "--json=v2"],
+                vec!["upgrade", "--formula", "llvm"],
+                vec!["upgrade", "--formula", "jq"]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_keep_caveats_of_packages_a_failed_batch_upgraded() {
+        let output = "==> Upgrading jq\n  1.7.1 -> 1.8.0\n==> Caveats\nzsh completions have been installed\n\
+            ==> Upgrading llvm\n  20.1.8 -> 21.1.0\n";
+        let failure = BrewError::FailedWithOutput { reason: "exit 1".to_string(), output: output.to_string() };
+        let llvm_outdated = r#"{"formulae": [{"name": "llvm", "installed_versions": ["20.1.8"], "current_version": "21.1.0"}]}"#;
+        let mock = MockBrewCommand::new()
+            .with_timeout_response(Err(failure))
+            .with_timeout_response(Err(BrewError::ExecutionFailed("exit 1".to_string())))
+            .with_execute_response(Ok(llvm_outdated.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        assert!(reports[0].is_failed());
+        assert_eq!(
+            reports[1],
+            PackageReport::new("jq", UpgradeStatus::Upgraded).with_caveats(Some("zsh completions have been installed".to_string()))
+        );
+        mock.assert_call_count(3);
+    }
+
+    #[tokio::test]
+    async fn should_fetch_every_package_and_drop_failed_downloads_from_plan() {
+        let mock = MockBrewCommand::new().with_timeout_response(Err(BrewError::ExecutionFailed("network down".to_string())));
+        let config = Config { fetch: FetchConfig { concurrency: 1, ..FetchConfig::default() }, ..Config::default() };
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let (fetched, failed) = system_under_test.fetch_packages(&plan.packages).await;
+        assert_eq!(fetched.iter().map(|p| p.name()).collect::<Vec<_>>(), vec!["jq"]);
+        assert_eq!(failed[0].name, "llvm");
+        assert!(matches!(failed[0].status, UpgradeStatus::FetchFailed(_)));
+        mock.assert_command_called(&["fetch", "--formula", "llvm"]);
+        mock.assert_command_called(&["fetch", "--formula", "jq"]);
+        assert!(mock.get_captured_commands().iter().all(|c| c.timeout == Some(Duration::minutes(15))));
+    }
+
+    #[tokio::test]
+    async fn should_include_selected_greedy_casks_and_upgrade_them_greedily() {
+        let greedy_json = r#"{"formulae": [], "casks": [
+            {"name": "google-chrome", "installed_versions": ["140.0"], "current_version": "141.0"},
+            {"name": "slack", "installed_versions": ["4.45"], "current_version": "4.46"}
+        ]}"#;
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok(JQ_OUTDATED_JSON.to_string()))
+            .with_execute_response(Ok(greedy_json.to_string()));
+        let config = Config {
+            casks: CaskConfig { greedy_auto_updates: vec!["google-chrome".to_string()], ..CaskConfig::default() },
+            ..Config::default()
+        };
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let outdated = system_under_test.find_outdated_packages().unwrap();
+        assert_eq!(outdated.iter().map(|p| p.name()).collect::<Vec<_>>(), vec!["jq", "google-chrome"]);
+        mock.assert_command_called(&["outdated", "--json=v2", "--cask", "--greedy-auto-updates"]);
+
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        mock.assert_command_called(&["upgrade", "--formula", "jq"]);
+        mock.assert_command_called(&["upgrade", "--cask", "--greedy-auto-updates", "google-chrome"]);
+    }
+
+    #[tokio::test]
+    async fn should_report_a_formula_and_a_cask_sharing_a_name_in_plan_order() {
+        let outdated: OutdatedPackages = serde_json::from_str(
+            r#"{"formulae": [
+                {"name": "jq", "installed_versions": ["1.7.1"], "current_version": "1.8.0"},
+                {"name": "docker", "installed_versions": ["28.3.3"], "current_version": "28.4.0"}
+            ], "casks": [{"name": "docker", "installed_versions": ["4.44.3"], "current_version": "4.45.0"}]}"#,
+        )
+        .unwrap();
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock);
+        let (jq, docker) = (&outdated.formulae[0], &outdated.formulae[1]);
+        let plan = UpgradePlan::new(vec![Package::Formula(jq), Package::Cask(&outdated.casks[0]), Package::Formula(docker)]);
+        let reports = system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        let order: Vec<_> = reports.iter().map(|r| (r.kind, r.name.as_str())).collect();
+        assert_eq!(order, vec![(PackageKind::Formula, "jq"), (PackageKind::Cask, "docker"), (PackageKind::Formula, "docker")]);
+    }
+
+    fn running_llvm() -> Vec<RunningProcess> {
+        let executable = paths::homebrew_prefix().join("Cellar/llvm/20.1.8/bin/clangd");
+        vec![RunningProcess { pid: 4242, executable }]
+    }
+
+    #[tokio::test]
+    async fn should_defer_running_package_then_skip_it_when_still_running() {
+        let mock = MockBrewCommand::new().with_execute_response(Ok(OUTDATED_JSON.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock).with_process_lister(running_llvm);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_with_timeout(&plan, &mut History::default()).await.unwrap();
+        assert_eq!(reports[0], PackageReport::new("jq", UpgradeStatus::Upgraded));
+        assert_eq!(reports[1].name, "llvm");
+        assert!(matches!(&reports[1].status, UpgradeStatus::Skipped(reason) if reason.contains("4242")));
+        mock.assert_call_count(2);
+        mock.assert_command_called(&["upgrade", "--formula", "jq"]);
+    }
+
+    #[tokio::test]
+    async fn should_upgrade_running_package_when_policy_ignores_it() {
+        let mut config = Config::default();
+        config.running_programs.packages.insert("llvm".to_string(), RunningProgramPolicy::Ignore);
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config).with_process_lister(running_llvm);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        assert!(reports.iter().all(|r| r.status == UpgradeStatus::Upgraded));
+        mock.assert_command_called(&["upgrade", "--formula", "llvm", "jq"]);
+    }
+
+    #[tokio::test]
+    async fn should_attach_caveats_of_the_upgrade_output_to_each_package() {
+        let output = "==> Upgrading llvm\n  20.1.7 -> 20.1.8\n==> Caveats\nTo use the bundled libc++ add to LDFLAGS\n==> Summary\n\
+            ==> Upgrading jq\n  1.7 -> 1.7.1\n==> Summary\n";
+        let mock = MockBrewCommand::new().with_timeout_response(Ok(output.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = UpgradePlan::new(outdated.iter().collect());
+        let reports = system_under_test.upgrade_packages_in_batch(&plan, &mut History::default()).await.unwrap();
+        let caveats: Vec<_> = reports.iter().map(|r| (r.name.as_str(), r.caveats.as_deref())).collect();
+        assert_eq!(caveats, vec![("llvm", Some("To use the bundled libc++ add to LDFLAGS")), ("jq", None)]);
+    }
+
+    #[tokio::test]
+    async fn should_restart_started_services_of_upgraded_formulae_and_report_failures() {
+        let started = vec![
+            BrewService { name: "llvm".to_string(), status: "started".to_string(), ..BrewService::default() },
+            BrewService { name: "jq".to_string(), status: "started".to_string(), ..BrewService::default() },
+            BrewService { name: "redis".to_string(), status: "started".to_string(), ..BrewService::default() },
+        ];
+        let upgraded = vec![
+            PackageReport::new("llvm", UpgradeStatus::Upgraded),
+            PackageReport::new("jq", UpgradeStatus::UpgradedAsDependency),
+            PackageReport::new("redis", UpgradeStatus::Failed("exit 1".to_string())),
+        ];
+        let mock = MockBrewCommand::new()
+            .with_timeout_response(Ok("==> Successfully restarted `llvm`".to_string()))
+            .with_execute_response(Ok(r#"[{"name": "llvm", "status": "started"}]"#.to_string()))
+            .with_timeout_response(Ok("==> Successfully restarted `jq`".to_string()))
+            .with_execute_response(Ok(r#"[{"name": "jq", "status": "error", "exit_code": 1}]"#.to_string()))
+            .with_execute_response(Ok(r#"[{"name": "jq", "status": "error", "exit_code": 1}]"#.to_string()));
+        let config = Config {
+            services: ServicesConfig { verify_attempts: 2, verify_interval_seconds: 0, ..ServicesConfig::default() },
+            ..Config::default()
+        };
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let reports = system_under_test.restart_services(&started, &upgraded).await;
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].restarted);
+        assert!(!reports[1].restarted);
+        assert_eq!(reports[1].detail, "status is error after 2 check(s)");
+        mock.assert_command_called(&["services", "restart", "llvm"]);
+        mock.assert_command_called(&["services", "list", "--json"]);
+        mock.assert_call_count(5);
+        assert_eq!(mock.get_captured_commands()[0].timeout, Some(Duration::seconds(60)));
+    }
+
+    #[tokio::test]
+    async fn should_mark_upgraded_package_unhealthy_when_a_smoke_test_fails() {
+        let mut config = Config::default();
+        let check = |command: &str| SmokeTest {
+            command: command.to_string(),
+            expected_exit_code: 0,
+            output_regex: None,
+            timeout_seconds: 5,
+        };
+        config.smoke_tests.insert("llvm".to_string(), vec![check("true"), check("exit 1")]);
+        config.smoke_tests.insert("jq".to_string(), vec![check("true")]);
+        config.smoke_tests.insert("wget".to_string(), vec![check("exit 1")]);
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let mut reports = vec![
+            PackageReport::new("llvm", UpgradeStatus::Upgraded),
+            PackageReport::new("jq", UpgradeStatus::Upgraded),
+            PackageReport::new("wget", UpgradeStatus::Failed("exit 1".to_string())),
+        ];
+        system_under_test.run_smoke_tests(&mut reports).await;
+        assert!(matches!(&reports[0].status, UpgradeStatus::Unhealthy(reason) if reason.contains("`exit 1` exited with 1")));
+        assert_eq!(reports[1].status, UpgradeStatus::Upgraded);
+        assert!(reports[2].is_failed());
+    }
+
+    #[tokio::test]
+    async fn should_report_and_reinstall_dependents_with_broken_linkage() {
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok("node\nphp\n".to_string()))
+            .with_execute_response(Ok("php\n".to_string()))
+            .with_execute_response(Ok(String::new()))
+            .with_execute_response(Err(BrewError::ExecutionFailed(
+                "Broken dependencies:\n  /opt/homebrew/opt/icu4c/lib/libicuuc.74.dylib (icu4c)".to_string(),
+            )));
+        let mut config = Config::default();
+        config.linkage.reinstall_broken = true;
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let reports = system_under_test.verify_linkage(&["icu4c", "openssl@3"], &History::default()).await;
+        assert_eq!(
+            reports,
+            vec![LinkageReport {
+                name: "php".to_string(),
+                upgraded_dependencies: vec!["icu4c".to_string(), "openssl@3".to_string()],
+                broken: vec!["/opt/homebrew/opt/icu4c/lib/libicuuc.74.dylib (icu4c)".to_string()],
+                reinstall: Some(Ok(())),
+            }]
+        );
+        mock.assert_command_called(&["uses", "--installed", "--recursive", "--formula", "icu4c"]);
+        mock.assert_command_called(&["linkage", "--test", "node"]);
+        mock.assert_command_called(&["reinstall", "--formula", "php"]);
+        mock.assert_call_count(5);
+    }
+
+    #[test]
+    fn should_plan_dependencies_first_using_installed_info() {
+        let info = r#"{"formulae": [
+            {"name": "llvm", "full_name": "llvm", "dependencies": ["jq"]},
+            {"name": "jq", "full_name": "jq", "dependencies": []}
+        ], "casks": []}"#;
+        let mock = MockBrewCommand::new().with_execute_response(Ok(info.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let info = system_under_test.installed_info().unwrap();
+        let plan = system_under_test.plan_upgrades(&outdated, Some(&info));
+        assert_eq!(plan.packages.iter().map(|p| p.name()).collect::<Vec<_>>(), vec!["jq", "llvm"]);
+        mock.assert_command_called(&["info", "--json=v2", "--installed"]);
+    }
+
+    #[tokio::test]
+    async fn should_only_refresh_outdated_packages_when_a_queued_one_is_related() {
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [
+                {"name": "llvm", "full_name": "llvm", "dependencies": []},
+                {"name": "jq", "full_name": "jq", "dependencies": []}
+            ]}"#,
+        )
+        .unwrap();
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let plan = system_under_test.plan_upgrades(&outdated, Some(&info));
+        let reports = system_under_test.upgrade_packages_with_timeout(&plan, &mut History::default()).await.unwrap();
+        assert!(reports.iter().all(|r| r.status == UpgradeStatus::Upgraded));
+        mock.assert_call_count(2);
+        assert!(mock.get_captured_commands().iter().all(|cmd| cmd.args[0] == "upgrade"));
+    }
+
+    #[tokio::test]
+    async fn should_skip_source_builds_and_extend_timeout_of_allowed_ones() {
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [
+                {"name": "llvm", "full_name": "llvm", "bottle": {"stable": {"files": {"arm64_tahoe": {}, "sonoma": {}}}}},
+                {"name": "jq", "full_name": "jq", "bottle": {"stable": {"files": {"arm64_sonoma": {}}}}}
+            ]}"#,
+        )
+        .unwrap();
+        let mock = MockBrewCommand::new().with_execute_response(Ok(JQ_OUTDATED_JSON.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let mut plan = system_under_test.plan_upgrades(&outdated, None);
+        let avoided = system_under_test.avoid_source_builds(&mut plan, &info, "arm64_sequoia", Weekday::Mon);
+        assert!(avoided.is_empty());
+        system_under_test.upgrade_packages_with_timeout(&plan, &mut History::default()).await.unwrap();
+        assert_eq!(mock.get_captured_commands()[0].timeout, Some(Duration::minutes(240)));
+
+        let mut config = Config::default();
+        config.timeouts.overrides.insert("llvm".to_string(), 600);
+        let mock = MockBrewCommand::new().with_execute_response(Ok(JQ_OUTDATED_JSON.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let mut plan = system_under_test.plan_upgrades(&outdated, None);
+        system_under_test.avoid_source_builds(&mut plan, &info, "arm64_sequoia", Weekday::Mon);
+        system_under_test.upgrade_packages_with_timeout(&plan, &mut History::default()).await.unwrap();
+        assert_eq!(mock.get_captured_commands()[0].timeout, Some(Duration::minutes(600)));
+
+        let config = Config {
+            source_builds: SourceBuildConfig { policy: SourceBuildPolicy::Skip, ..SourceBuildConfig::default() },
+            ..Config::default()
+        };
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let mut plan = system_under_test.plan_upgrades(&outdated, None);
+        let avoided = system_under_test.avoid_source_builds(&mut plan, &info, "arm64_sequoia", Weekday::Mon);
+        // the sonoma bottle of jq is poured on sequoia, llvm has none for this architecture
+        assert_eq!(avoided.len(), 1);
+        assert_eq!(avoided[0].name, "llvm");
+        assert_eq!(plan.packages.iter().map(|p| p.name()).collect::<Vec<_>>(), vec!["jq"]);
+    }
+
+    #[test]
+    fn should_retain_replaced_kegs_and_spare_them_from_cleanup() {
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock);
+        let outdated = outdated_packages();
+        let reports =
+            vec![PackageReport::new("llvm", UpgradeStatus::Upgraded), PackageReport::new("jq", UpgradeStatus::Failed("x".into()))];
+        let mut history = History::default();
+        system_under_test.retain_previous_kegs(&outdated, &reports, &mut history);
+        assert_eq!(history.retained_kegs.iter().map(|keg| keg.name.as_str()).collect::<Vec<_>>(), vec!["llvm"]);
+
+        system_under_test.cleanup(&mut history).unwrap();
+        let cleanup = &mock.get_captured_commands()[0];
+        assert_eq!(cleanup.envs.get("HOMEBREW_NO_CLEANUP_FORMULAE").map(String::as_str), Some("llvm"));
+    }
+
+    #[test]
+    fn should_snapshot_brewfile_and_read_installed_info_only_when_missing() {
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok("brew \"jq\"\n".to_string()))
+            .with_execute_response(Ok("brew \"jq\"\n".to_string()))
+            .with_execute_response(Ok(r#"{"formulae": [{"name": "jq", "installed": [{"version": "1.8.0"}]}]}"#.to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+
+        let before = system_under_test.snapshot("before", Some(&InstalledInfo::default())).unwrap();
+        assert_eq!(before.brewfile, "brew \"jq\"\n");
+        assert!(before.formulae.is_empty());
+        mock.assert_call_count(1);
+        mock.assert_command_called(&["bundle", "dump", "--file=-"]);
+
+        let after = system_under_test.snapshot("after", None).unwrap();
+        assert_eq!(after.formulae.get("jq").map(String::as_str), Some("1.8.0"));
+        mock.assert_call_count(3);
+    }
+
+    #[tokio::test]
+    async fn should_install_missing_brewfile_entries_and_uninstall_unlisted_leaves() {
+        let brewfile = std::env::temp_dir().join(format!("brew-maintainer-Brewfile-{}", std::process::id()));
+        std::fs::write(&brewfile, "tap \"hashicorp/tap\"\nbrew \"jq\"\nbrew \"terraform\"\nbrew \"hashicorp/tap/packer\"\n")
+            .unwrap();
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok("homebrew/core\n".to_string()))
+            .with_execute_response(Ok("hashicorp/tap/terraform\njq\nwget\n".to_string()))
+            .with_execute_response(Ok(r#"{"formulae": [
+                {"name": "jq"}, {"name": "wget"}, {"name": "terraform", "full_name": "hashicorp/tap/terraform"}
+            ]}"#
+            .to_string()))
+            .with_execute_response(Err(BrewError::ExecutionFailed(
+                "brew bundle can't satisfy your Brewfile's dependencies.".into(),
+            )));
+        let mut config = Config::default();
+        config.reconcile.uninstall_unlisted_leaves = true;
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+
+        let report = system_under_test.reconcile(&brewfile).await.unwrap();
+
+        assert_eq!(report.diff.missing_taps, vec!["hashicorp/tap"]);
+        assert_eq!(report.diff.missing_formulae, vec!["hashicorp/tap/packer"]);
+        assert_eq!(report.diff.unlisted_leaves, vec!["wget"]);
+        assert_eq!(report.install, Some(Ok(())));
+        assert_eq!(report.uninstalled, vec![("wget".to_string(), Ok(()))]);
+        mock.assert_command_called(&["bundle", "install", "--no-upgrade", "--file", brewfile.to_str().unwrap()]);
+        mock.assert_command_called(&["uninstall", "--formula", "wget"]);
+        std::fs::remove_file(&brewfile).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_only_report_brewfile_differences_in_report_only_mode() {
+        let brewfile = std::env::temp_dir().join(format!("brew-maintainer-Brewfile-report-{}", std::process::id()));
+        std::fs::write(&brewfile, "brew \"jq\"\n").unwrap();
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok(String::new()))
+            .with_execute_response(Ok("wget\n".to_string()))
+            .with_execute_response(Ok(r#"{"formulae": [{"name": "wget"}]}"#.to_string()));
+        let mut config = Config::default();
+        config.reconcile.report_only = true;
+        config.reconcile.uninstall_unlisted_leaves = true;
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+
+        let report = system_under_test.reconcile(&brewfile).await.unwrap();
+
+        assert_eq!(report.diff.missing_formulae, vec!["jq"]);
+        assert_eq!(report.diff.unlisted_leaves, vec!["wget"]);
+        assert_eq!((report.install, report.uninstalled), (None, vec![]));
+        mock.assert_call_count(3);
+        std::fs::remove_file(&brewfile).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_keep_unlisted_leaves_when_brew_bundle_install_fails() {
+        let brewfile = std::env::temp_dir().join(format!("brew-maintainer-Brewfile-failed-{}", std::process::id()));
+        std::fs::write(&brewfile, "brew \"jq\"\n").unwrap();
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok(String::new()))
+            .with_execute_response(Ok("wget\n".to_string()))
+            .with_execute_response(Ok(r#"{"formulae": [{"name": "wget"}]}"#.to_string()))
+            .with_execute_response(Err(BrewError::ExecutionFailed("jq is not installed".into())))
+            .with_timeout_response(Err(BrewError::ExecutionFailed("Error: No such file".into())));
+        let mut config = Config::default();
+        config.reconcile.uninstall_unlisted_leaves = true;
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+
+        let report = system_under_test.reconcile(&brewfile).await.unwrap();
+
+        assert!(matches!(report.install, Some(Err(_))));
+        assert!(report.uninstalled.is_empty());
+        mock.assert_call_count(5);
+        std::fs::remove_file(&brewfile).unwrap();
+    }
+
+    #[test]
+    fn should_autoremove_orphans_except_protected_ones_and_record_them() {
+        let preview = "==> Would autoremove 5 unneeded formulae:\nlibsigsegv\nlibyaml\nm4\npcre\nzlib\n";
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok(preview.to_string()))
+            .with_execute_response(Ok(String::new()))
+            .with_execute_response(Ok(String::new()))
+            .with_execute_response(Err(BrewError::ExecutionFailed("Permission denied".to_string())));
+        let mut config = Config::default();
+        config.autoremove.protect = vec!["m4".to_string()];
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [{"name": "libyaml", "linked_keg": "0.2.5"}, {"name": "m4", "dependencies": ["libsigsegv"]},
+                {"name": "libsigsegv"}, {"name": "pcre", "dependencies": ["libyaml"]}, {"name": "zlib"}]}"#,
+        )
+        .unwrap();
+        let mut history = History::default();
+
+        let report = system_under_test.autoremove(Some(&info), &mut history);
+
+        // pcre depends on libyaml: it is uninstalled first
+        assert_eq!(report.removed, vec!["pcre", "libyaml"]);
+        assert_eq!(report.protected, vec!["m4", "libsigsegv"]);
+        assert_eq!(report.failed, vec![("zlib".to_string(), "Error executing the brew command: Permission denied".to_string())]);
+        assert!(report.error.is_none());
+        let uninstalled: Vec<String> = mock.get_captured_commands()[1..].iter().map(|cmd| cmd.args[2].clone()).collect();
+        assert_eq!(uninstalled, vec!["pcre", "libyaml", "zlib"]);
+        mock.assert_call_count(4);
+        assert_eq!(history.autoremoved.len(), 2);
+        assert_eq!(history.autoremoved[1].version.as_deref(), Some("0.2.5"));
+    }
+
+    #[test]
+    fn should_run_brew_autoremove_when_no_orphan_is_protected() {
+        let mock = MockBrewCommand::new().with_execute_response(Ok("==> Would autoremove 1 unneeded formula:\nm4\n".to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let report = system_under_test.autoremove(None, &mut History::default());
+        assert_eq!(report.removed, vec!["m4"]);
+        mock.assert_command_called(&["autoremove"]);
+        mock.assert_call_count(2);
+    }
+
+    #[test]
+    fn should_apply_cleanup_policy_and_account_reclaimed_space() {
+        let prefix = std::env::temp_dir().join(format!("brew-maintainer-cleanup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&prefix);
+        // modification times in the opposite order of the versions, as after touching the older kegs
+        let epoch = std::time::SystemTime::UNIX_EPOCH;
+        for (age, version) in ["3.11.9", "3.12.7", "3.13.0", "3.13.1"].into_iter().enumerate() {
+            let keg = prefix.join("Cellar/python").join(version);
+            std::fs::create_dir_all(&keg).unwrap();
+            let modified = epoch + StdDuration::from_secs(1_000_000 - age as u64 * 1000);
+            std::fs::File::open(&keg).unwrap().set_modified(modified).unwrap();
+        }
+        for version in ["20.19.0", "22.11.0", "22.12.0"] {
+            std::fs::create_dir_all(prefix.join("Cellar/node").join(version)).unwrap();
+        }
+        std::fs::create_dir_all(prefix.join("opt")).unwrap();
+        std::os::unix::fs::symlink("../Cellar/python/3.13.1", prefix.join("opt/python")).unwrap();
+        std::os::unix::fs::symlink("../Cellar/node/22.12.0", prefix.join("opt/node")).unwrap();
+        kegs::point_pin_to(&prefix, "node", "20.19.0").unwrap();
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok("==> This operation has freed approximately 2.0MB of disk space.\n".to_string()));
+        let mut config = Config::default();
+        config.cleanup.prune = Some("30".to_string());
+        config.cleanup.scrub = true;
+        config.cleanup.keep_versions.insert("python".to_string(), 1);
+        config.cleanup.keep_versions.insert("node".to_string(), 0);
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config).with_prefix(prefix.clone());
+        let mut history = History { reclaimed_bytes: 1024, ..Default::default() };
+
+        let report = system_under_test.cleanup(&mut history).unwrap();
+
+        mock.assert_command_called(&["cleanup", "--prune", "30", "-s"]);
+        let cleanup = &mock.get_captured_commands()[0];
+        assert_eq!(cleanup.envs.get("HOMEBREW_NO_CLEANUP_FORMULAE").map(String::as_str), Some("node,python"));
+        assert_eq!(report.reclaimed_bytes, Some(2 * 1024 * 1024));
+        assert_eq!(report.removed_kegs, vec!["python 3.12.7", "python 3.11.9"]);
+        assert!(report.errors.is_empty());
+        assert_eq!(kegs::installed_versions(&prefix, "python"), vec!["3.13.1", "3.13.0"]);
+        // node is pinned: its pinned keg and the others survive
+        assert_eq!(kegs::installed_versions(&prefix, "node"), vec!["22.12.0", "22.11.0", "20.19.0"]);
+        assert_eq!(history.reclaimed_bytes, 2 * 1024 * 1024 + 1024);
+        std::fs::remove_dir_all(&prefix).unwrap();
+    }
+
+    #[test]
+    fn should_clean_up_then_leave_out_largest_upgrades_when_disk_space_is_insufficient() {
+        let prefix = std::env::temp_dir().join(format!("brew-maintainer-space-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&prefix);
+        std::fs::create_dir_all(prefix.join("Cellar/llvm/20.1.8")).unwrap();
+        std::fs::write(prefix.join("Cellar/llvm/20.1.8/libLLVM.dylib"), vec![0u8; 3000]).unwrap();
+        let info: InstalledInfo =
+            serde_json::from_str(r#"{"formulae": [{"name": "jq", "bottle": {"stable": {"files": {"all": {"size": 500}}}}}]}"#)
+                .unwrap();
+        let cache = prefix.to_str().unwrap().to_string();
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok(cache.clone()))
+            .with_execute_response(Ok("==> This operation has freed approximately 10B of disk space.".to_string()))
+            .with_execute_response(Ok(cache));
+        let mut config = Config::default();
+        config.disk_space.margin_mb = 0;
+        let system_under_test =
+            BrewMaintainer::new(&mock).with_config(config).with_prefix(prefix.clone()).with_free_space(|_| Ok(4000));
+        let outdated: OutdatedPackages = serde_json::from_str(
+            r#"{
+                "formulae": [
+                    {"name": "llvm", "installed_versions": ["20.1.8"], "current_version": "21.1.0"},
+                    {"name": "jq", "installed_versions": ["1.7.1"], "current_version": "1.8.0"}
+                ],
+                "casks": [{"name": "llvm", "installed_versions": ["1.0"], "current_version": "1.1"}]
+            }"#,
+        )
+        .unwrap();
+        let mut plan = system_under_test.plan_upgrades(&outdated, None);
+        let mut history = History::default();
+
+        let (reports, cleanup) = system_under_test.ensure_disk_space(&mut plan, Some(&info), Some("arm64_sequoia"), &mut history);
+
+        assert_eq!(reports.len(), 1);
+        assert!(matches!(&reports[0].status, UpgradeStatus::InsufficientDiskSpace(reason) if reason.contains("2.9KB")));
+        assert_eq!(reports[0].name, "llvm");
+        let planned: Vec<_> = plan.packages.iter().map(|p| p.key()).collect();
+        assert_eq!(planned, vec![(PackageKind::Formula, "jq"), (PackageKind::Cask, "llvm")]);
+        mock.assert_call_count(3);
+        assert_eq!(cleanup.and_then(|cleanup| cleanup.reclaimed_bytes), Some(10));
+        assert_eq!(history.reclaimed_bytes, 10);
+        std::fs::remove_dir_all(&prefix).unwrap();
+    }
+
+    #[test]
+    fn should_report_only_new_doctor_warnings_and_degrade_the_run() {
+        let doctor = "Warning: Your Command Line Tools are too outdated.\nUpdate them.\n\n\
+                      Warning: Some installed formulae are deprecated or disabled.\n";
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Err(BrewError::ExecutionFailed(doctor.to_string())))
+            .with_execute_response(Ok("HOMEBREW_VERSION: 4.6.17\nGit: N/A\n".to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let mut history = History {
+            doctor_warnings: vec!["Some installed formulae are deprecated or disabled.".to_string()],
+            ..Default::default()
+        };
+
+        let report = system_under_test.health_check(&mut history).unwrap();
+
+        assert_eq!(report.new_warnings.len(), 1);
+        assert_eq!(report.new_warnings[0].title, "Your Command Line Tools are too outdated.");
+        assert_eq!(report.known_warnings, 1);
+        assert!(report.blocking.is_empty());
+        assert!(report.skip_update && report.skip_source_builds);
+        assert_eq!(history.doctor_warnings.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_migrate_deprecated_packages_declaring_a_replacement() {
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [
+                {"name": "youtube-dl", "deprecated": true, "deprecation_replacement_formula": "yt-dlp"},
+                {"name": "python@3.9", "disabled": true, "disable_reason": "versioned_formula"}
+            ]}"#,
+        )
+        .unwrap();
+        let mock = MockBrewCommand::new();
+        let mut config = Config::default();
+        config.deprecations.migrate = true;
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+
+        let reports = system_under_test.migrate_deprecated(deprecations::find(&info), &History::default()).await;
+
+        assert_eq!(reports[0].migration, Some(Ok(())));
+        assert_eq!(reports[1].migration, None);
+        let args: Vec<Vec<String>> = mock.get_captured_commands().into_iter().map(|c| c.args).collect();
+        assert_eq!(args, vec![vec!["install", "--formula", "yt-dlp"], vec!["uninstall", "--formula", "youtube-dl"]]);
+    }
+
+    #[test]
+    fn should_relink_and_pin_retained_keg_on_rollback() {
+        let prefix = std::env::temp_dir().join(format!("brew-maintainer-rollback-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&prefix);
+        for version in ["1.7", "1.7.1", "1.8.0"] {
+            std::fs::create_dir_all(prefix.join("Cellar/jq").join(version)).unwrap();
+        }
+        std::fs::create_dir_all(prefix.join("opt")).unwrap();
+        std::os::unix::fs::symlink("../Cellar/jq/1.8.0", prefix.join("opt/jq")).unwrap();
+        let mut history = History::default();
+        history.retain_keg("jq", "1.7.1", Utc::now());
+        let mock = MockBrewCommand::new();
+        let system_under_test = BrewMaintainer::new(&mock).with_prefix(prefix.clone());
+
+        let record = system_under_test.rollback("jq", &mut history).unwrap();
+
+        assert_eq!((record.from_version.as_str(), record.to_version.as_str()), ("1.8.0", "1.7.1"));
+        assert_eq!(kegs::linked_version(&prefix, "jq").as_deref(), Some("1.7.1"));
+        let args: Vec<Vec<String>> = mock.get_captured_commands().into_iter().map(|c| c.args).collect();
+        assert_eq!(args, vec![vec!["unlink", "jq"], vec!["link", "jq"], vec!["pin", "jq"]]);
+        let pin = std::fs::read_link(prefix.join("var/homebrew/pinned/jq")).unwrap();
+        assert_eq!(pin, Path::new("../../../Cellar/jq/1.7.1"));
+        assert_eq!(history.rollbacks, vec![record]);
+        assert!(history.retained_kegs.is_empty());
+        std::fs::remove_dir_all(&prefix).unwrap();
+    }
+
+    #[test]
+    fn should_enforce_configured_pins_release_expired_ones_and_report_held_formulae() {
+        let now = Utc::now();
+        let mut config = Config::default();
+        config.pins.insert("wget".to_string(), PinRule { until: None });
+        config.pins.insert("node".to_string(), PinRule { until: Some(now.date_naive() - Duration::days(1)) });
+        let mut history = History::default();
+        let record =
+            |name: &str, expires_at, configured| PinRecord { name: name.to_string(), pinned_at: now, expires_at, configured };
+        history.record_pin(record("node", Some(now - Duration::hours(1)), true));
+        history.record_pin(record("jq", None, true));
+        history.record_pin(record("ruby", None, false));
+        let outdated: OutdatedPackages = serde_json::from_str(
+            r#"{"formulae": [
+                {"name": "llvm", "installed_versions": ["20.1.8"], "current_version": "21.1.0", "pinned": true, "pinned_version": "20.1.8"}
+            ], "casks": []}"#,
+        )
+        .unwrap();
+        let mock = MockBrewCommand::new().with_execute_response(Ok("jq\nllvm\nnode\n".to_string()));
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let report = system_under_test.enforce_pins(&outdated, None, &mut history, now).unwrap();
+        assert_eq!(report.pinned, vec!["wget"]);
+        assert_eq!(
+            report.unpinned,
+            vec![("jq".to_string(), "no longer configured".to_string()), ("node".to_string(), "pin expired".to_string())]
+        );
+        assert!(report.errors.is_empty());
+        let held: Vec<_> = report.held.iter().map(|h| (h.name.as_str(), h.version.as_deref(), h.available.as_deref())).collect();
+        assert_eq!(held, vec![("llvm", Some("20.1.8"), Some("21.1.0")), ("wget", None, None)]);
+        mock.assert_command_called(&["list", "--pinned"]);
+        mock.assert_command_called(&["pin", "wget"]);
+        mock.assert_command_called(&["unpin", "node"]);
+        mock.assert_command_called(&["unpin", "jq"]);
+        mock.assert_call_count(4);
+        let pins: Vec<_> = history.pins.iter().map(|pin| pin.name.as_str()).collect();
+        assert_eq!(pins, vec!["wget"]);
+    }
+
+    #[test]
+    fn should_release_a_pin_expired_and_no_longer_configured_once() {
+        let now = Utc::now();
+        let mut history = History::default();
+        let expires_at = Some(now - Duration::hours(1));
+        history.record_pin(PinRecord { name: "node".to_string(), pinned_at: now, expires_at, configured: true });
+        let mock = MockBrewCommand::new().with_execute_response(Ok("node\n".to_string()));
+        let system_under_test = BrewMaintainer::new(&mock);
+        let report = system_under_test.enforce_pins(&OutdatedPackages::default(), None, &mut history, now).unwrap();
+        assert_eq!(report.unpinned, vec![("node".to_string(), "pin expired".to_string())]);
+        assert!(report.errors.is_empty());
+        mock.assert_command_called(&["unpin", "node"]);
+        mock.assert_call_count(2);
+        assert!(history.pins.is_empty());
+    }
+
+    #[test]
+    fn should_leave_pins_made_by_hand_to_the_user() {
+        let now = Utc::now();
+        let mut config = Config::default();
+        config.pins.insert("ruby".to_string(), PinRule { until: None });
+        let mut history = History::default();
+        let expires_at = Some(now + Duration::days(7));
+        history.record_pin(PinRecord { name: "ruby".to_string(), pinned_at: now, expires_at, configured: false });
+        let mock = MockBrewCommand::new().with_execute_response(Ok("ruby\n".to_string()));
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let report = system_under_test.enforce_pins(&OutdatedPackages::default(), None, &mut history, now).unwrap();
+        assert!(report.pinned.is_empty());
+        mock.assert_call_count(1);
+        let pin = history.pin("ruby").unwrap();
+        assert_eq!((pin.expires_at, pin.configured), (expires_at, false));
+    }
+
+    #[test]
+    fn should_flag_untrusted_unused_and_failing_taps_and_untap_unused_ones() {
+        let tap_info = r#"[
+            {"name": "homebrew/core", "official": true, "remote": "https://github.com/Homebrew/homebrew-core"},
+            {"name": "hashicorp/tap", "official": false, "remote": "https://github.com/hashicorp/homebrew-tap"},
+            {"name": "acme/tools", "official": false, "remote": "https://git.acme.test/homebrew-tools"},
+            {"name": "evil/tap", "official": false}
+        ]"#;
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [{"name": "terraform", "full_name": "hashicorp/tap/terraform", "tap": "hashicorp/tap"}]}"#,
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.taps.trusted = vec!["acme/*".to_string()];
+        config.taps.untap_unused = true;
+        let mock = MockBrewCommand::new()
+            .with_execute_response(Ok(tap_info.to_string()))
+            .with_execute_response(Ok(String::new()))
+            .with_execute_response(Err(BrewError::ExecutionFailed("Error: Refusing to untap".to_string())));
+        let system_under_test = BrewMaintainer::new(&mock).with_config(config);
+        let update_output = "Error: Fetching /opt/homebrew/Library/Taps/acme/homebrew-tools failed!";
+        let report = system_under_test.audit_taps(&info, Some(update_output)).unwrap();
+        assert_eq!(report.failed_updates, vec!["acme/tools"]);
+        assert_eq!(
+            report.untrusted,
+            vec![
+                ("hashicorp/tap".to_string(), Some("https://github.com/hashicorp/homebrew-tap".to_string())),
+                ("evil/tap".to_string(), None)
+            ]
+        );
+        assert_eq!(report.unused, vec!["acme/tools", "evil/tap"]);
+        assert_eq!(report.untapped, vec!["acme/tools"]);
+        assert_eq!(report.errors, vec!["cannot untap evil/tap: Error executing the brew command: Error: Refusing to untap"]);
+        mock.assert_command_called(&["tap-info", "--json", "--installed"]);
+        mock.assert_command_called(&["untap", "acme/tools"]);
+        mock.assert_call_count(3);
+    }
+
     pub struct MockBrewCommand {
         /// Captured commands that were executed
         pub captured_commands: Arc<Mutex<Vec<CapturedCommand>>>,
         /// Configured responses for execute()
         pub execute_responses: Arc<Mutex<Vec<Result<String, BrewError>>>>,
         /// Configured responses for execute_with_timeout()
-        pub timeout_responses: Arc<Mutex<Vec<Result<(), BrewError>>>>,
+        pub timeout_responses: Arc<Mutex<Vec<Result<String, BrewError>>>>,
         /// Simulated delay before returning (for timeout testing)
         pub simulated_delay: Option<StdDuration>,
     }
@@ -140,7 +1977,7 @@ mod tests {
             self.execute_responses.lock().unwrap().push(response);
             self
         }
-        pub fn with_timeout_response(self, response: Result<(), BrewError>) -> Self {
+        pub fn with_timeout_response(self, response: Result<String, BrewError>) -> Self {
             self.timeout_responses.lock().unwrap().push(response);
             self
         }
@@ -187,7 +2024,9 @@ mod tests {
             envs
         }
 
-        async fn execute_with_timeout<'a>(&self, cmd: &BrewCommand<'a>, timeout: Duration) -> std::result::Result<(), BrewError> {
+        async fn execute_with_timeout<'a>(
+            &self, cmd: &BrewCommand<'a>, timeout: Duration,
+        ) -> std::result::Result<String, BrewError> {
             let args = cmd.to_args();
             let env_map = cmd.to_env();
 
@@ -206,7 +2045,7 @@ mod tests {
 
             // Return configured response or default success
             let mut responses = self.timeout_responses.lock().unwrap();
-            if !responses.is_empty() { responses.remove(0) } else { Ok(()) }
+            if !responses.is_empty() { responses.remove(0) } else { Ok(String::new()) }
         }
     }
 }
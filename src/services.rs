@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// Entry of `brew services list --json`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BrewService {
+    /// Name of the formula providing the service
+    pub name: String,
+    /// `started`, `stopped`, `none`, `error`, `scheduled`, ...
+    pub status: String,
+    pub user: Option<String>,
+    pub file: Option<String>,
+    pub pid: Option<u32>,
+    pub exit_code: Option<i32>,
+}
+
+impl BrewService {
+    pub fn is_started(&self) -> bool {
+        self.status == "started"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn should_parse_brew_services_list() {
+        let output = r#"[
+            {"name": "postgresql@16", "status": "started", "user": "luca", "file": "~/Library/LaunchAgents/homebrew.mxcl.postgresql@16.plist", "exit_code": 0},
+            {"name": "redis", "status": "none", "user": null, "file": "/opt/homebrew/opt/redis/homebrew.mxcl.redis.plist", "exit_code": null},
+            {"name": "unbound", "status": "error", "user": "root", "file": "/Library/LaunchDaemons/homebrew.mxcl.unbound.plist", "exit_code": 256}
+        ]"#;
+        let services: Vec<BrewService> = parser::parse_json(output).unwrap();
+        assert_eq!(services.iter().filter(|s| s.is_started()).map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["postgresql@16"]);
+        assert_eq!(services[2].exit_code, Some(256));
+    }
+}
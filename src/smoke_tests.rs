@@ -0,0 +1,86 @@
+use std::{process::Stdio, time::Duration as StdDuration};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command as TokioCommand;
+
+/// Command checking that a package works after being upgraded
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmokeTest {
+    /// Shell command, run with `sh -c`
+    pub command: String,
+    #[serde(default)]
+    pub expected_exit_code: i32,
+    /// Regex that stdout or stderr must match
+    #[serde(default)]
+    pub output_regex: Option<String>,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+fn default_timeout_seconds() -> u64 {
+    30
+}
+
+impl SmokeTest {
+    /// Runs the command, returning why it failed if it did
+    pub async fn run(&self) -> Result<(), String> {
+        let pattern = match &self.output_regex {
+            Some(pattern) => Some(Regex::new(pattern).map_err(|e| format!("invalid output regex {:?}: {}", pattern, e))?),
+            None => None,
+        };
+        let child = TokioCommand::new("sh")
+            .args(["-c", &self.command])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("`{}` cannot be started: {}", self.command, e))?;
+        let output = tokio::time::timeout(StdDuration::from_secs(self.timeout_seconds), child.wait_with_output())
+            .await
+            .map_err(|_| format!("`{}` timed out after {}s", self.command, self.timeout_seconds))?
+            .map_err(|e| format!("`{}` failed: {}", self.command, e))?;
+
+        let exit_code = output.status.code().unwrap_or(-1);
+        if exit_code != self.expected_exit_code {
+            return Err(format!("`{}` exited with {} instead of {}", self.command, exit_code, self.expected_exit_code));
+        }
+        let combined = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        match pattern {
+            Some(pattern) if !pattern.is_match(&combined) => {
+                Err(format!("`{}` output does not match {:?}", self.command, pattern.as_str()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smoke_test(command: &str, output_regex: Option<&str>) -> SmokeTest {
+        SmokeTest {
+            command: command.to_string(),
+            expected_exit_code: 0,
+            output_regex: output_regex.map(String::from),
+            timeout_seconds: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_pass_when_exit_code_and_output_match() {
+        assert_eq!(smoke_test("echo v22.11.0", Some(r"^v\d+\.")).run().await, Ok(()));
+        assert_eq!(smoke_test("echo warning >&2", Some("warning")).run().await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn should_fail_on_unexpected_exit_code_output_or_timeout() {
+        assert!(smoke_test("exit 3", None).run().await.unwrap_err().contains("exited with 3 instead of 0"));
+        assert!(smoke_test("echo 3.12", Some("^3\\.13")).run().await.unwrap_err().contains("does not match"));
+        let slow = SmokeTest { timeout_seconds: 0, ..smoke_test("sleep 5", None) };
+        assert!(slow.run().await.unwrap_err().contains("timed out"));
+        assert!(smoke_test("true", Some("(")).run().await.unwrap_err().contains("invalid output regex"));
+    }
+}
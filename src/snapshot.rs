@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::info::InstalledInfo;
+
+/// Installed packages at some point of a run: the `brew bundle dump` Brewfile and the exact installed versions
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+    /// Moment of the run, `before` or `after`
+    pub label: String,
+    pub brewfile: String,
+    /// Linked version of each installed formula
+    pub formulae: BTreeMap<String, String>,
+    pub casks: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    pub fn new(label: &str, brewfile: String, info: &InstalledInfo, taken_at: DateTime<Utc>) -> Self {
+        let formulae = info.formulae.iter().filter_map(|f| Some((f.name.clone(), f.installed_version()?))).collect();
+        let casks = info.casks.iter().filter_map(|c| Some((c.token.clone(), c.installed.clone()?))).collect();
+        Self { taken_at, label: label.to_string(), brewfile, formulae, casks }
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let content = fs::read_to_string(path).with_context(|| format!("cannot read snapshot {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("invalid snapshot in {}", path.display()))
+    }
+
+    /// Writes the snapshot in the directory under a name sorting chronologically, e.g. `20261018T020000Z-before.json`
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+        let path = dir.join(format!("{}-{}.json", self.taken_at.format("%Y%m%dT%H%M%SZ"), self.label));
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content).with_context(|| format!("cannot write snapshot {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Snapshot files of the directory, oldest first
+pub fn list(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> =
+        entries.flatten().map(|entry| entry.path()).filter(|path| path.extension().is_some_and(|ext| ext == "json")).collect();
+    paths.sort();
+    paths
+}
+
+/// Removes the oldest snapshots beyond the `keep` most recent ones
+pub fn prune(dir: &Path, keep: usize) -> Result<()> {
+    let paths = list(dir);
+    for path in &paths[..paths.len().saturating_sub(keep)] {
+        fs::remove_file(path).with_context(|| format!("cannot remove snapshot {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// A snapshot given on the command line: a path, or a file name of the snapshot directory with or without extension
+pub fn resolve(dir: &Path, name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.exists() {
+        return path;
+    }
+    let in_dir = dir.join(name);
+    if in_dir.extension().is_some_and(|ext| ext == "json") { in_dir } else { dir.join(format!("{}.json", name)) }
+}
+
+/// The snapshots to compare: the named ones, or the two most recent ones
+pub fn select(dir: &Path, names: Option<(&str, &str)>) -> Result<(Snapshot, Snapshot)> {
+    let (before, after) = match names {
+        Some((before, after)) => (resolve(dir, before), resolve(dir, after)),
+        None => match list(dir).as_slice() {
+            [.., before, after] => (before.clone(), after.clone()),
+            _ => bail!("fewer than two snapshots in {}", dir.display()),
+        },
+    };
+    Ok((Snapshot::load(&before)?, Snapshot::load(&after)?))
+}
+
+/// Version of a package in the compared snapshots, `None` when it is not installed
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageChange {
+    pub name: String,
+    pub is_cask: bool,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnapshotDiff {
+    pub packages: Vec<PackageChange>,
+    pub brewfile_added: Vec<String>,
+    pub brewfile_removed: Vec<String>,
+}
+
+impl SnapshotDiff {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> Self {
+        let mut packages = version_changes(&before.formulae, &after.formulae, false);
+        packages.extend(version_changes(&before.casks, &after.casks, true));
+        let lines_before: BTreeSet<&str> = brewfile_lines(&before.brewfile).collect();
+        let lines_after: BTreeSet<&str> = brewfile_lines(&after.brewfile).collect();
+        Self {
+            packages,
+            brewfile_added: lines_after.difference(&lines_before).map(|line| line.to_string()).collect(),
+            brewfile_removed: lines_before.difference(&lines_after).map(|line| line.to_string()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.brewfile_added.is_empty() && self.brewfile_removed.is_empty()
+    }
+}
+
+fn version_changes(before: &BTreeMap<String, String>, after: &BTreeMap<String, String>, is_cask: bool) -> Vec<PackageChange> {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| PackageChange {
+            name: name.clone(),
+            is_cask,
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned(),
+        })
+        .collect()
+}
+
+fn brewfile_lines(brewfile: &str) -> impl Iterator<Item = &str> {
+    brewfile.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+impl Display for PackageChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = if self.is_cask { format!("{} (cask)", self.name) } else { self.name.clone() };
+        match (&self.before, &self.after) {
+            (None, Some(after)) => writeln!(f, "\t + {} {}", name, after),
+            (Some(before), None) => writeln!(f, "\t - {} {}", name, before),
+            (Some(before), Some(after)) => writeln!(f, "\t ~ {} {} => {}", name, before, after),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl Display for SnapshotDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        if !self.packages.is_empty() {
+            writeln!(f, "packages:")?;
+            for change in &self.packages {
+                write!(f, "{}", change)?;
+            }
+        }
+        if !self.brewfile_added.is_empty() || !self.brewfile_removed.is_empty() {
+            writeln!(f, "brewfile:")?;
+            for line in &self.brewfile_added {
+                writeln!(f, "\t + {}", line)?;
+            }
+            for line in &self.brewfile_removed {
+                writeln!(f, "\t - {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> InstalledInfo {
+        serde_json::from_str(
+            r#"{
+                "formulae": [
+                    {"name": "jq", "linked_keg": "1.7.1", "installed": [{"version": "1.7"}, {"version": "1.7.1"}]},
+                    {"name": "wget", "installed": [{"version": "1.24.5"}]}
+                ],
+                "casks": [{"token": "firefox", "installed": "143.0.1"}, {"token": "docker", "installed": null}]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_record_linked_or_latest_installed_versions() {
+        let snapshot = Snapshot::new("before", String::new(), &info(), Utc::now());
+        assert_eq!(snapshot.formulae.get("jq").map(String::as_str), Some("1.7.1"));
+        assert_eq!(snapshot.formulae.get("wget").map(String::as_str), Some("1.24.5"));
+        assert_eq!(snapshot.casks.keys().collect::<Vec<_>>(), vec!["firefox"]);
+    }
+
+    #[test]
+    fn should_diff_versions_and_brewfile_entries() {
+        let before =
+            Snapshot::new("before", "tap \"homebrew/core\"\nbrew \"jq\"\nbrew \"wget\"\n".to_string(), &info(), Utc::now());
+        let mut after = before.clone();
+        after.formulae.insert("jq".to_string(), "1.8.0".to_string());
+        after.formulae.remove("wget");
+        after.casks.insert("docker".to_string(), "4.48.0".to_string());
+        after.brewfile = "tap \"homebrew/core\"\nbrew \"jq\"\ncask \"docker\"\n".to_string();
+
+        let diff = SnapshotDiff::between(&before, &after);
+
+        assert_eq!(diff.packages.len(), 3);
+        assert_eq!(diff.brewfile_added, vec!["cask \"docker\""]);
+        assert_eq!(diff.brewfile_removed, vec!["brew \"wget\""]);
+        let display = diff.to_string();
+        assert!(display.contains("\t ~ jq 1.7.1 => 1.8.0"));
+        assert!(display.contains("\t - wget 1.24.5"));
+        assert!(display.contains("\t + docker (cask) 4.48.0"));
+    }
+
+    #[test]
+    fn should_save_snapshots_sorted_by_time_and_prune_oldest() {
+        let dir = std::env::temp_dir().join(format!("brew-maintainer-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let taken_at = Utc::now();
+        let before = Snapshot::new("before", String::new(), &info(), taken_at);
+        let after = Snapshot::new("after", String::new(), &info(), taken_at + chrono::Duration::minutes(30));
+        let before_path = before.save(&dir).unwrap();
+        let after_path = after.save(&dir).unwrap();
+        assert_eq!(list(&dir), vec![before_path.clone(), after_path.clone()]);
+        let name = after_path.file_stem().unwrap().to_str().unwrap();
+        assert_eq!(resolve(&dir, name), after_path);
+        assert_eq!(Snapshot::load(&after_path).unwrap(), after);
+
+        prune(&dir, 1).unwrap();
+        assert_eq!(list(&dir), vec![after_path]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
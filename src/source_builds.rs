@@ -0,0 +1,67 @@
+use chrono::{Duration, Weekday};
+
+use crate::{
+    config::{SourceBuildConfig, SourceBuildPolicy},
+    info::FormulaInfo,
+};
+
+/// What to do with a planned formula, depending on the availability of a bottle
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceBuildDecision {
+    /// A bottle will be poured
+    Bottle,
+    /// Build from source with the given timeout
+    Build(Duration),
+    /// Do not upgrade, with the reason
+    Avoid(String),
+}
+
+pub fn decide(config: &SourceBuildConfig, formula: &FormulaInfo, bottle_tag: &str, today: Weekday) -> SourceBuildDecision {
+    if formula.has_bottle_for(bottle_tag) {
+        return SourceBuildDecision::Bottle;
+    }
+    let build = SourceBuildDecision::Build(Duration::minutes(config.timeout_minutes as i64));
+    match config.policy {
+        SourceBuildPolicy::Allow => build,
+        SourceBuildPolicy::Defer if in_window(config, today) => build,
+        SourceBuildPolicy::Defer => SourceBuildDecision::Avoid(format!(
+            "no bottle for {}, source build deferred to {}",
+            bottle_tag,
+            config.window_days.join("/")
+        )),
+        SourceBuildPolicy::Skip => SourceBuildDecision::Avoid(format!("no bottle for {}, source builds are disabled", bottle_tag)),
+    }
+}
+
+fn in_window(config: &SourceBuildConfig, today: Weekday) -> bool {
+    config.window_days.iter().filter_map(|day| day.parse::<Weekday>().ok()).any(|day| day == today)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(bottle_tags: &[&str]) -> FormulaInfo {
+        let files: serde_json::Map<String, serde_json::Value> = bottle_tags
+            .iter()
+            .map(|tag| (tag.to_string(), serde_json::json!({"cellar": ":any", "url": "u", "sha256": "s"})))
+            .collect();
+        serde_json::from_value(serde_json::json!({"name": "qt", "bottle": {"stable": {"rebuild": 0, "files": files}}})).unwrap()
+    }
+
+    #[test]
+    fn should_pour_bottle_when_available_for_tag_or_all() {
+        let config = SourceBuildConfig { policy: SourceBuildPolicy::Skip, ..SourceBuildConfig::default() };
+        assert_eq!(decide(&config, &formula(&["arm64_sequoia"]), "arm64_sequoia", Weekday::Mon), SourceBuildDecision::Bottle);
+        assert_eq!(decide(&config, &formula(&["all"]), "sonoma", Weekday::Mon), SourceBuildDecision::Bottle);
+        assert_eq!(decide(&config, &formula(&["arm64_sonoma"]), "arm64_sequoia", Weekday::Mon), SourceBuildDecision::Bottle);
+    }
+
+    #[test]
+    fn should_defer_source_builds_outside_window() {
+        let config = SourceBuildConfig { policy: SourceBuildPolicy::Defer, ..SourceBuildConfig::default() };
+        let qt = formula(&["arm64_tahoe", "sonoma"]);
+        assert!(matches!(decide(&config, &qt, "arm64_sequoia", Weekday::Wed), SourceBuildDecision::Avoid(_)));
+        assert_eq!(decide(&config, &qt, "arm64_sequoia", Weekday::Sun), SourceBuildDecision::Build(Duration::minutes(240)));
+    }
+}
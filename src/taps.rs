@@ -0,0 +1,93 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::info::InstalledInfo;
+
+/// Tap as listed by `brew tap-info --json --installed`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TapInfo {
+    pub name: String,
+    /// Maintained by Homebrew (`homebrew/*`)
+    pub official: bool,
+    pub remote: Option<String>,
+    pub custom_remote: bool,
+    pub path: String,
+    /// External commands the tap provides (e.g. `brew bundle`)
+    pub command_files: Vec<String>,
+}
+
+impl TapInfo {
+    /// Whether an installed formula or cask comes from the tap, or the tap provides brew commands
+    pub fn is_used(&self, info: &InstalledInfo) -> bool {
+        let from_tap = |tap: &Option<String>| tap.as_deref().is_some_and(|tap| tap.eq_ignore_ascii_case(&self.name));
+        !self.command_files.is_empty()
+            || info.formulae.iter().any(|formula| from_tap(&formula.tap))
+            || info.casks.iter().any(|cask| from_tap(&cask.tap))
+    }
+}
+
+/// Taps `brew update` failed to fetch, from its `Error: Fetching <tap path> failed!` lines
+pub fn failed_updates(output: &str) -> Vec<String> {
+    let pattern = Regex::new(r"Fetching \S*/Taps/([^/\s]+)/(?:homebrew-)?([^/\s]+) failed").expect("valid regex");
+    pattern.captures_iter(output).map(|captures| format!("{}/{}", &captures[1], &captures[2]).to_lowercase()).collect()
+}
+
+/// Whether `tap` is matched by one of the `trusted` entries, a tap name or `owner/*` for every tap of an owner
+pub fn is_trusted(tap: &str, trusted: &[String]) -> bool {
+    trusted.iter().any(|entry| match entry.strip_suffix("/*") {
+        Some(owner) => tap.split('/').next().is_some_and(|tap_owner| tap_owner.eq_ignore_ascii_case(owner)),
+        None => entry.eq_ignore_ascii_case(tap),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const TAP_INFO_JSON: &str = r#"[
+        {"name": "homebrew/core", "user": "Homebrew", "repo": "core", "official": true, "installed": true,
+         "formula_names": [], "command_files": [], "remote": "https://github.com/Homebrew/homebrew-core", "custom_remote": false,
+         "path": "/opt/homebrew/Library/Taps/homebrew/homebrew-core"},
+        {"name": "hashicorp/tap", "user": "hashicorp", "repo": "tap", "official": false, "installed": true,
+         "formula_names": ["hashicorp/tap/terraform"], "command_files": [], "remote": "https://github.com/hashicorp/homebrew-tap",
+         "custom_remote": false, "path": "/opt/homebrew/Library/Taps/hashicorp/homebrew-tap"},
+        {"name": "acme/tools", "official": false, "command_files": ["cmd/acme.rb"], "path": "/opt/homebrew/Library/Taps/acme/homebrew-tools"}
+    ]"#;
+
+    #[test]
+    fn should_parse_taps_and_tell_used_ones() {
+        let taps: Vec<TapInfo> = parser::parse_json(TAP_INFO_JSON).unwrap();
+        assert_eq!(taps.len(), 3);
+        assert!(taps[0].official);
+        assert_eq!(taps[1].remote.as_deref(), Some("https://github.com/hashicorp/homebrew-tap"));
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [{"name": "jq", "full_name": "jq", "tap": "homebrew/core"}],
+                "casks": [{"token": "vagrant", "full_token": "hashicorp/tap/vagrant", "tap": "hashicorp/tap"}]}"#,
+        )
+        .unwrap();
+        assert!(taps[0].is_used(&info));
+        assert!(taps[1].is_used(&info));
+        assert!(taps[2].is_used(&InstalledInfo::default()));
+        assert!(!taps[1].is_used(&InstalledInfo::default()));
+    }
+
+    #[test]
+    fn should_find_taps_that_failed_to_update() {
+        let output = "Error: Fetching /opt/homebrew/Library/Taps/acme/homebrew-tools failed!\n\
+            Error: Fetching /home/linuxbrew/.linuxbrew/Homebrew/Library/Taps/Hashicorp/homebrew-tap failed!\n\
+            Already up-to-date.";
+        assert_eq!(failed_updates(output), vec!["acme/tools", "hashicorp/tap"]);
+        assert!(failed_updates("Already up-to-date.").is_empty());
+    }
+
+    #[test]
+    fn should_trust_listed_taps_and_owners() {
+        let trusted = vec!["hashicorp/tap".to_string(), "acme/*".to_string()];
+        assert!(is_trusted("hashicorp/tap", &trusted));
+        assert!(is_trusted("acme/tools", &trusted));
+        assert!(!is_trusted("hashicorp/other", &trusted));
+        assert!(!is_trusted("evil/acme", &trusted));
+    }
+}
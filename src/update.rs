@@ -0,0 +1,200 @@
+use std::fmt::Display;
+
+use crate::info::InstalledInfo;
+
+/// Changes reported by `brew update`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdateSummary {
+    pub taps: Vec<String>,
+    pub new_formulae: Vec<String>,
+    pub new_casks: Vec<String>,
+    pub updated_formulae: Vec<String>,
+    pub updated_casks: Vec<String>,
+    /// Old and new names
+    pub renamed_formulae: Vec<(String, String)>,
+    pub deleted_formulae: Vec<String>,
+    pub deleted_casks: Vec<String>,
+    /// Renamed formulae installed under their old or new name
+    pub installed_renamed: Vec<(String, String)>,
+    /// Deleted formulae and casks still installed
+    pub installed_deleted: Vec<String>,
+}
+
+impl UpdateSummary {
+    pub fn parse(output: &str) -> Self {
+        let mut summary = UpdateSummary::default();
+        let mut section: Option<&str> = None;
+        for line in output.lines().map(str::trim) {
+            if let Some(title) = line.strip_prefix("==> ") {
+                section = Some(title);
+                continue;
+            }
+            if line.is_empty() {
+                section = None;
+                continue;
+            }
+            let tap_list =
+                line.strip_prefix("Updated ").filter(|rest| rest.contains(" tap")).and_then(|rest| rest.split_once(" ("));
+            if let Some((_, taps)) = tap_list {
+                summary.taps =
+                    taps.trim_end_matches(['.', ')']).split(", ").flat_map(|taps| taps.split(" and ")).map(String::from).collect();
+                continue;
+            }
+            let items = match section {
+                Some("New Formulae") => &mut summary.new_formulae,
+                Some("New Casks") => &mut summary.new_casks,
+                Some("Updated Formulae" | "Modified Formulae") => &mut summary.updated_formulae,
+                Some("Updated Casks" | "Modified Casks") => &mut summary.updated_casks,
+                // brew only lists the deleted packages that are installed unless it reports all of them
+                Some("Deleted Formulae" | "Deleted Installed Formulae") => &mut summary.deleted_formulae,
+                Some("Deleted Casks" | "Deleted Installed Casks") => &mut summary.deleted_casks,
+                Some("Renamed Formulae") => {
+                    if let Some((old, new)) = line.split_once("->") {
+                        summary.renamed_formulae.push((old.trim().to_string(), new.trim().to_string()));
+                    }
+                    continue;
+                }
+                // `Outdated Formulae` and `Outdated Casks` list installed packages, `brew outdated` reports them
+                _ => continue,
+            };
+            // new packages are listed as `name: description`, other lists in columns of names
+            if let Some((name, _)) = line.split_once(':') {
+                items.push(name.trim().to_string());
+            } else if !line.ends_with('.') {
+                // counts such as `Updated 42 formulae.` replace the lists when brew is not verbose
+                items.extend(line.split_whitespace().map(String::from));
+            }
+        }
+        summary
+    }
+
+    /// Notes the renamed and deleted packages that are installed locally
+    pub fn highlight_installed(&mut self, info: &InstalledInfo) {
+        self.installed_renamed = self
+            .renamed_formulae
+            .iter()
+            .filter(|(old, new)| info.formula(old).is_some() || info.formula(new).is_some())
+            .cloned()
+            .collect();
+        self.installed_deleted = self
+            .deleted_formulae
+            .iter()
+            .filter(|name| info.formula(name).is_some())
+            .chain(self.deleted_casks.iter().filter(|token| info.cask(token).is_some()))
+            .cloned()
+            .collect();
+    }
+}
+
+impl Display for UpdateSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.taps.is_empty() {
+            writeln!(f, "\t taps updated: {}", self.taps.join(", "))?;
+        }
+        writeln!(
+            f,
+            "\t {} new formulae, {} new casks, {} updated formulae, {} updated casks",
+            self.new_formulae.len(),
+            self.new_casks.len(),
+            self.updated_formulae.len(),
+            self.updated_casks.len()
+        )?;
+        for (old, new) in &self.renamed_formulae {
+            let installed = if self.installed_renamed.iter().any(|(o, _)| o == old) { " (installed)" } else { "" };
+            writeln!(f, "\t renamed: {} -> {}{}", old, new, installed)?;
+        }
+        for name in self.deleted_formulae.iter().chain(&self.deleted_casks) {
+            let installed = if self.installed_deleted.contains(name) { " (installed)" } else { "" };
+            writeln!(f, "\t deleted: {}{}", name, installed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Default `brew update` report of Homebrew 4, written to a file so lists are printed one name per line
+    const UPDATE: &str = "==> Updating Homebrew...
+Updated 2 taps (homebrew/core and homebrew/cask).
+==> New Formulae
+kubectx-rs: Faster way to switch between clusters and namespaces in kubectl
+uv-tools: Helpers for the uv Python package manager
+==> New Casks
+ghostty@tip: Terminal emulator that uses platform-native UI and GPU acceleration
+==> Deleted Installed Formulae
+vault
+==> Deleted Installed Casks
+atom
+==> Outdated Formulae
+jq
+node
+==> Outdated Casks
+slack
+
+You have 2 outdated formulae and 1 outdated cask installed.
+You can upgrade them with brew upgrade
+or list them with brew outdated.
+";
+
+    /// Report of all the changed packages, with `HOMEBREW_UPDATE_REPORT_ALL_FORMULAE` set
+    const UPDATE_ALL: &str = "==> Updating Homebrew...
+Updated 3 taps (hashicorp/tap, homebrew/core and homebrew/cask).
+==> New Formulae
+kubectx-rs: Faster way to switch between clusters and namespaces in kubectl
+==> Modified Formulae
+hashicorp/tap/terraform
+jq
+node
+==> Modified Casks
+firefox
+==> Renamed Formulae
+youtube-dl -> yt-dlp
+==> Deleted Formulae
+vault
+";
+
+    #[test]
+    fn should_parse_sections_of_brew_update() {
+        let summary = UpdateSummary::parse(UPDATE);
+        assert_eq!(summary.taps, vec!["homebrew/core", "homebrew/cask"]);
+        assert_eq!(summary.new_formulae, vec!["kubectx-rs", "uv-tools"]);
+        assert_eq!(summary.new_casks, vec!["ghostty@tip"]);
+        assert!(summary.updated_formulae.is_empty());
+        assert!(summary.updated_casks.is_empty());
+        assert_eq!(summary.deleted_formulae, vec!["vault"]);
+        assert_eq!(summary.deleted_casks, vec!["atom"]);
+        assert_eq!(UpdateSummary::parse("Already up-to-date.\n"), UpdateSummary::default());
+
+        let summary = UpdateSummary::parse(UPDATE_ALL);
+        assert_eq!(summary.taps, vec!["hashicorp/tap", "homebrew/core", "homebrew/cask"]);
+        assert_eq!(summary.new_formulae, vec!["kubectx-rs"]);
+        assert_eq!(summary.updated_formulae, vec!["hashicorp/tap/terraform", "jq", "node"]);
+        assert_eq!(summary.updated_casks, vec!["firefox"]);
+        assert_eq!(summary.renamed_formulae, vec![("youtube-dl".to_string(), "yt-dlp".to_string())]);
+        assert_eq!(summary.deleted_formulae, vec!["vault"]);
+    }
+
+    #[test]
+    fn should_highlight_installed_renamed_and_deleted_packages() {
+        let info: InstalledInfo = serde_json::from_str(
+            r#"{"formulae": [{"name": "youtube-dl"}, {"name": "vault"}, {"name": "jq"}], "casks": [{"token": "atom"}]}"#,
+        )
+        .unwrap();
+        let mut summary = UpdateSummary::parse(UPDATE);
+        summary.highlight_installed(&info);
+        assert_eq!(summary.installed_deleted, vec!["vault", "atom"]);
+        let display = summary.to_string();
+        assert!(display.contains("\t deleted: vault (installed)"));
+        assert!(display.contains("\t deleted: atom (installed)"));
+
+        let mut summary = UpdateSummary::parse(UPDATE_ALL);
+        summary.highlight_installed(&InstalledInfo { formulae: info.formulae[..1].to_vec(), casks: vec![] });
+        assert_eq!(summary.installed_renamed.len(), 1);
+        assert!(summary.installed_deleted.is_empty());
+        let display = summary.to_string();
+        assert!(display.contains("\t renamed: youtube-dl -> yt-dlp (installed)"));
+        assert!(display.contains("\t deleted: vault\n"));
+    }
+}